@@ -1,8 +1,114 @@
+#include <stddef.h>
 #include <stdint.h>
 
+/* error */
+typedef enum {
+    ELF_OK = 0,
+    /* also sizes or alignments of sections that overflow */
+    ELF_ERR_PARSE = 1,
+    ELF_ERR_UNDEFINED_SYMBOL = 2,
+    ELF_ERR_SYMBOL_CONFLICT = 3,
+    /* also TLS (TPREL_*) and misaligned ALIGN, modules have no TLS block and aren't relaxed */
+    ELF_ERR_UNSUPPORTED_RELOCATION = 4,
+    ELF_ERR_RELOCATION = 5,
+    ELF_ERR_NO_MEMORY = 6,
+    ELF_ERR_COMMON_SYMBOL = 7,
+    ELF_ERR_INIT_FAILED = 8,
+    ELF_ERR_PROTECT_FAILED = 9,
+} elf_error_t;
+
+/* default regions in heap, rust_elf_add_region returns ids after them */
+#define ELF_REGION_TEXT 0
+#define ELF_REGION_DATA 1
+
+/* SHF_* of ELF, for rust_elf_add_placement */
+#define ELF_SHF_WRITE 0x1
+#define ELF_SHF_ALLOC 0x2
+#define ELF_SHF_EXECINSTR 0x4
+
+/* permissions for elf_protect_fn, same values as mprotect */
+#define ELF_PROT_READ 0x1
+#define ELF_PROT_WRITE 0x2
+#define ELF_PROT_EXEC 0x4
+
+/* set `prot` of `size` bytes at `addr`, return 0 on success */
+typedef int (*elf_protect_fn)(void *ctx, void *addr, size_t size, uint32_t prot);
+
+/* make `size` bytes at `addr` visible to instruction fetch */
+typedef void (*elf_cache_sync_fn)(void *ctx, const void *addr, size_t size);
+
+/* return `size` bytes aligned to `align` or NULL if out of memory */
+typedef void *(*elf_region_alloc_fn)(void *ctx, size_t size, size_t align);
+typedef void (*elf_region_free_fn)(void *ctx, void *ptr);
+
+/* where an address lies, `symbol` is NULL if no symbol is before it */
+typedef struct {
+    const void *module;
+    size_t region;
+    const char *symbol;
+    const void *symbol_addr;
+    size_t offset;
+} elf_addr_info_t;
+
+/* return address of `name` or NULL if unknown */
+typedef const void *(*elf_resolve_fn)(void *ctx, const char *name);
+/* `count` names which are still missing, the load fails after it */
+typedef void (*elf_missing_fn)(void *ctx, const char *const *names, size_t count);
+
+/* STT_* and STB_* of ELF */
+#define ELF_STT_NOTYPE 0
+#define ELF_STT_OBJECT 1
+#define ELF_STT_FUNC 2
+#define ELF_STB_LOCAL 0
+#define ELF_STB_GLOBAL 1
+#define ELF_STB_WEAK 2
+
+/* a defined symbol and the module which defines it */
+typedef struct {
+    const void *module;
+    const void *addr;
+    size_t size;
+    uint8_t type;
+    uint8_t bind;
+} elf_sym_info_t;
+
+/* fill `len` bytes at `offset` of the image into `buf`, return 0 on success */
+typedef int (*elf_read_fn)(void *ctx, size_t offset, void *buf, size_t len);
+
+/* host symbol visible to loaded modules, same layout as `ExportSymbol` */
+typedef struct {
+    const char *name;
+    const void *addr;
+} elf_export_t;
+
+/* put `sym` into the export table, the linker provides bounds of the section */
+#define ELF_EXPORT_SYMBOL(sym)                                                 \
+    static const elf_export_t __elf_export_##sym                               \
+        __attribute__((used, section("elfloader_exports"))) = {#sym, (const void *)&sym}
+
 /* api */
 void *rust_elf_load(const void *elf_buf);
+void *rust_elf_load_buf(const void *elf_buf, const size_t len);
+void *rust_elf_load_ex(const void *elf_buf, const size_t len, elf_error_t *err);
+void *rust_elf_load_stream(elf_read_fn read, void *ctx, const size_t size, elf_error_t *err);
+elf_error_t rust_elf_last_error(void);
+const char *rust_elf_strerror(const elf_error_t err);
 void *rust_elf_sym(const void *handle, const uint8_t *sym_name);
+size_t rust_elf_skipped_relas(const void *handle);
+const char *rust_elf_skipped_rela(const void *handle, const size_t idx);
+int rust_elf_sym_info(const void *handle, const uint8_t *sym_name, elf_sym_info_t *info);
+elf_error_t rust_elf_register_symbol(const char *name, const void *addr);
+elf_error_t rust_elf_import_symbols(const void *elf_buf, const size_t len, const char *const *allow,
+                                    const size_t nallow);
+elf_error_t rust_elf_import_symbols_stream(elf_read_fn read, void *ctx, const size_t size,
+                                           const char *const *allow, const size_t nallow);
+void rust_elf_set_resolver(elf_resolve_fn resolve, elf_missing_fn missing, void *ctx);
+void rust_elf_set_load_debug(int enable);
+int rust_elf_add_region(elf_region_alloc_fn alloc, elf_region_free_fn free, void *ctx);
+int rust_elf_add_placement(const char *pattern, size_t flags_mask, size_t flags, int region);
+int rust_elf_set_protect(elf_protect_fn protect, void *ctx);
+void rust_elf_set_cache_sync(elf_cache_sync_fn sync, void *ctx);
+int rust_elf_addr_info(const void *addr, elf_addr_info_t *info);
 void rust_elf_unload(const void *handle);
 void rust_elf_modules(void);
 
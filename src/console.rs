@@ -11,6 +11,14 @@ impl Write for Console {
     }
 }
 
+/* host tests print to stdout */
+#[cfg(test)]
+#[no_mangle]
+extern "C" fn rust_console_putbytes(bs: *const u8, len: usize) {
+    let bs = unsafe { core::slice::from_raw_parts(bs, len) };
+    std::print!("{}", alloc::string::String::from_utf8_lossy(bs));
+}
+
 #[macro_export]
 macro_rules! print {
     ($($args:tt)+) => ({
@@ -1,7 +1,12 @@
 pub mod headers;
+pub mod reader;
+mod relocate;
 pub mod section;
 
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
+use core::mem;
 use core::ops::BitAnd;
 use core::ptr;
 use core::slice;
@@ -9,304 +14,573 @@ use core::slice;
 use headers::{EHeader, SHeader};
 use headers::{SHFlags, SHType};
 
+use reader::ElfRead;
+
 use section::{Rela, Symbol};
 use section::{RelaType, STBind, STType, STVis};
 
-#[derive(Debug)]
-pub struct ELFFile {
-    ehaddr: *const EHeader,
-    shaddr: *const [SHeader],
+enum ElfSource<'a> {
+    /* image is resident in memory, tables are borrowed from it */
+    Bytes(&'a [u8]),
+    /* image is pulled through reader, tables are copied */
+    Reader(&'a dyn ElfRead),
+}
+
+impl<'a> ElfSource<'a> {
+    fn size(&self) -> usize {
+        match *self {
+            ElfSource::Bytes(bytes) => bytes.len(),
+            ElfSource::Reader(reader) => reader.size(),
+        }
+    }
+
+    /* read exactly `buf.len()` bytes from `offset` of the image */
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<(), ELFFileError> {
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| end <= self.size())
+            .ok_or(ELFFileError::ReadFailed(offset))?;
+        match *self {
+            ElfSource::Bytes(bytes) => Ok(buf.copy_from_slice(&bytes[offset..end])),
+            ElfSource::Reader(reader) => reader.read(offset, buf),
+        }
+    }
+
+    /* T must be plain data of integers, any bit pattern is valid for it */
+    fn read_table<T: Clone>(
+        &self,
+        offset: usize,
+        count: usize,
+        err: ELFFileError,
+    ) -> Result<Cow<'a, [T]>, ELFFileError> {
+        let size = count
+            .checked_mul(mem::size_of::<T>())
+            .filter(|&size| {
+                offset
+                    .checked_add(size)
+                    .filter(|&end| end <= self.size())
+                    .is_some()
+            })
+            .ok_or(err)?;
+        if let ElfSource::Bytes(bytes) = *self {
+            let p = bytes[offset..].as_ptr();
+            if p.align_offset(mem::align_of::<T>()) == 0 {
+                return Ok(Cow::Borrowed(unsafe {
+                    slice::from_raw_parts(p as *const T, count)
+                }));
+            }
+        }
+        let mut table: Vec<T> = Vec::new();
+        table
+            .try_reserve_exact(count)
+            .map_err(|_| ELFFileError::OutOfMemory(size))?;
+        unsafe {
+            self.read_at(
+                offset,
+                slice::from_raw_parts_mut(table.as_mut_ptr() as *mut u8, size),
+            )?;
+            table.set_len(count);
+        }
+        Ok(Cow::Owned(table))
+    }
 }
 
+pub struct ELFFile<'a> {
+    source: ElfSource<'a>,
+    ehdr: EHeader,
+    shdrs: Cow<'a, [SHeader]>,
+    /* tables needed while loading, keyed by section index */
+    strtabs: BTreeMap<usize, Cow<'a, [u8]>>,
+    symtabs: BTreeMap<usize, Cow<'a, [Symbol]>>,
+    relatabs: BTreeMap<usize, Cow<'a, [Rela]>>,
+}
+
+/* section index or table entry index is carried to locate the bad offset */
 #[derive(Debug)]
 pub enum ELFFileError {
     FileNotFound,
     FileHasNotSection,
     FileNotValid,
+    ClassMismatch,
+    EndianMismatch,
+    MachineMismatch,
+    TypeNotRelocatable,
+    TypeNotExecutable,
+    FloatAbiMismatch(u32),
+    RvcNotSupported,
+    RveMismatch,
+    FileTooSmall,
+    ReadFailed(usize),
+    OutOfMemory(usize),
+    SectionHeaderSizeInvalid,
+    SectionHeaderOutOfBounds,
+    ShStrNdxOutOfBounds,
+    SectionOutOfBounds(usize),
+    SectionAlignInvalid(usize),
+    SectionEntSizeInvalid(usize),
+    SectionLinkOutOfBounds(usize),
+    SectionInfoOutOfBounds(usize),
+    StringOutOfBounds(usize),
+    StringNotValid(usize),
+    SymbolSectionOutOfBounds(usize),
+    SymbolValueOutOfBounds(usize),
+    CommonAlignInvalid(usize),
+    RelaSymbolOutOfBounds(usize),
+    RelaOffsetOutOfBounds(usize),
+}
+
+#[derive(Debug)]
+pub enum RelocateError {
+    Unsupported(RelaType),
+    /* encoded value at the place can't be patched, e.g. unterminated ULEB128 */
+    Malformed(RelaType, usize),
+    /* PCREL_LO12 refers to a label without PCREL_HI20 */
+    PcrelHi20NotFound(usize),
+    /* value doesn't fit the field or is misaligned */
+    Overflow(RelaType, usize),
 }
 
-impl ELFFile {
-    pub fn parse(elf_bin: *const u8) -> Result<ELFFile, ELFFileError> {
+impl<'a> ELFFile<'a> {
+    pub fn parse(elf_bin: *const u8, len: usize) -> Result<ELFFile<'a>, ELFFileError> {
+        if elf_bin.is_null() {
+            return Err(ELFFileError::FileNotFound);
+        }
+        let bytes = unsafe { slice::from_raw_parts(elf_bin, len) };
+        Self::parse_source(ElfSource::Bytes(bytes), true)
+    }
+
+    /* only headers and tables are kept, sections are read at loading */
+    pub fn from_reader(reader: &'a dyn ElfRead) -> Result<ELFFile<'a>, ELFFileError> {
+        Self::parse_source(ElfSource::Reader(reader), true)
+    }
+
+    /* executable image of the firmware, only its symbol tables are used,
+     * it may be stripped to symtab and strtab */
+    pub fn parse_executable(elf_bin: *const u8, len: usize) -> Result<ELFFile<'a>, ELFFileError> {
+        if elf_bin.is_null() {
+            return Err(ELFFileError::FileNotFound);
+        }
+        let bytes = unsafe { slice::from_raw_parts(elf_bin, len) };
+        Self::parse_source(ElfSource::Bytes(bytes), false)
+    }
+
+    pub fn executable_from_reader(reader: &'a dyn ElfRead) -> Result<ELFFile<'a>, ELFFileError> {
+        Self::parse_source(ElfSource::Reader(reader), false)
+    }
+
+    fn parse_source(source: ElfSource<'a>, relocatable: bool) -> Result<ELFFile<'a>, ELFFileError> {
         use self::ELFFileError::*;
+        let ehdr = source.read_table::<EHeader>(0, 1, FileTooSmall)?[0].clone();
+        if !ehdr.is_valid() {
+            return Err(FileNotValid);
+        }
+        ehdr.check_identity(relocatable)?;
+        let shnum = ehdr.e_shnum as usize;
+        if shnum < 1 {
+            return Err(FileHasNotSection);
+        }
+        if ehdr.e_shentsize as usize != mem::size_of::<SHeader>() {
+            return Err(SectionHeaderSizeInvalid);
+        }
+        let shdrs = source.read_table(ehdr.e_shoff, shnum, SectionHeaderOutOfBounds)?;
+        let mut elf_file = ELFFile {
+            source,
+            ehdr,
+            shdrs,
+            strtabs: BTreeMap::new(),
+            symtabs: BTreeMap::new(),
+            relatabs: BTreeMap::new(),
+        };
+        elf_file.validate(relocatable)?;
+        Ok(elf_file)
+    }
+
+    /* Size of the image as described by its own headers.
+     * Only for callers which cannot tell the buffer length, the headers are trusted. */
+    pub unsafe fn image_size(elf_bin: *const u8) -> usize {
         if elf_bin.is_null() {
-            return Err(FileNotFound);
+            return 0;
+        }
+        let ehdr = ptr::read_unaligned(elf_bin as *const EHeader);
+        if !ehdr.is_valid() || ehdr.e_shentsize as usize != mem::size_of::<SHeader>() {
+            return mem::size_of::<EHeader>();
         }
-        let elf_file = {
-            let ehaddr = elf_bin as *const EHeader;
-            let shaddr = unsafe {
-                let ehdr = &*ehaddr;
-                let shnum = ehdr.e_shnum as usize;
-                if shnum < 1 {
-                    return Err(FileHasNotSection);
+        let shoff = ehdr.e_shoff;
+        let shend = shoff.saturating_add(ehdr.e_shnum as usize * mem::size_of::<SHeader>());
+        (0..ehdr.e_shnum as usize)
+            .map(|idx| ptr::read_unaligned((elf_bin.add(shoff) as *const SHeader).add(idx)))
+            .filter(|sh| !matches!(sh.section_type(), SHType::NOBITS))
+            .map(|sh| sh.sh_offset.saturating_add(sh.sh_size))
+            .fold(shend.max(mem::size_of::<EHeader>()), usize::max)
+    }
+
+    /* relocations of an executable are not needed and their offsets are addresses */
+    fn validate(&mut self, relocatable: bool) -> Result<(), ELFFileError> {
+        use self::ELFFileError::*;
+        let shnum = self.section_headers().len();
+        let shstrndx = self.elf_header().e_shstrndx as usize;
+        if shstrndx >= shnum {
+            return Err(ShStrNdxOutOfBounds);
+        }
+        /* every section must lie in the image */
+        self.section_headers()
+            .iter()
+            .enumerate()
+            .try_for_each(|(idx, sh)| {
+                if !matches!(sh.section_type(), SHType::NOBITS) {
+                    sh.sh_offset
+                        .checked_add(sh.sh_size)
+                        .filter(|&secend| secend <= self.source.size())
+                        .ok_or(SectionOutOfBounds(idx))?;
+                }
+                if sh.sh_flags & (SHFlags::ALLOC as usize) != 0
+                    && sh.sh_addralign > 1
+                    && !sh.sh_addralign.is_power_of_two()
+                {
+                    return Err(SectionAlignInvalid(idx));
                 }
-                let shoff = ehdr.e_shoff;
-                let shdr = (ehaddr as *const u8).offset(shoff as isize) as *const SHeader;
-                ptr::addr_of!(*slice::from_raw_parts(shdr, shnum))
-            };
-            ELFFile { ehaddr, shaddr }
+                Ok(())
+            })?;
+        /* every section name must lie in shstrtab */
+        if shstrndx != 0 {
+            self.load_strtab(shstrndx)?;
+            self.section_headers()
+                .iter()
+                .try_for_each(|sh| self.string_at(shstrndx, sh.sh_name as usize).map(|_| ()))?;
+        }
+        let tabndxs = |shtype: SHType| {
+            (0..shnum)
+                .filter(|&idx| self.shdrs[idx].section_type() == shtype)
+                .collect::<Vec<_>>()
         };
-        if !elf_file.elf_header().is_valid() {
-            return Err(FileNotValid);
+        let (symtabndxs, relatabndxs) = match relocatable {
+            true => (tabndxs(SHType::SYMTAB), tabndxs(SHType::RELA)),
+            false => (tabndxs(SHType::SYMTAB), Vec::new()),
+        };
+        /* every symbol must have valid name and section */
+        symtabndxs.into_iter().try_for_each(|idx| {
+            let sh = self.shdrs[idx].clone();
+            let strndx = sh.sh_link as usize;
+            match self.shdrs.get(strndx).map(|strtab| strtab.section_type()) {
+                Some(SHType::STRTAB) => self.load_strtab(strndx)?,
+                _ => return Err(SectionLinkOutOfBounds(idx)),
+            }
+            let symtab = self.read_section_table::<Symbol>(idx)?;
+            symtab.iter().enumerate().try_for_each(|(symidx, s)| {
+                self.string_at(strndx, s.st_name as usize)?;
+                /* SHN_UNDEF, or SHN_ABS and SHN_COMMON of reserved indices */
+                match s.st_shndx as usize {
+                    /* SHN_COMMON takes alignment in st_value */
+                    0xfff2 if !s.st_value.is_power_of_two() => Err(CommonAlignInvalid(symidx)),
+                    /* offset in a section of a relocatable, an address in an executable */
+                    ndx if ndx > 0 && ndx < shnum => {
+                        match relocatable && s.st_value > self.shdrs[ndx].sh_size {
+                            true => Err(SymbolValueOutOfBounds(symidx)),
+                            false => Ok(()),
+                        }
+                    }
+                    ndx if ndx == 0 || ndx == 0xfff1 || ndx == 0xfff2 => Ok(()),
+                    _ => Err(SymbolSectionOutOfBounds(symidx)),
+                }
+            })?;
+            self.symtabs.insert(idx, symtab);
+            Ok(())
+        })?;
+        /* every relocation must refer to a symbol and patch inside its section */
+        relatabndxs.into_iter().try_for_each(|idx| {
+            let sh = &self.shdrs[idx];
+            let nsyms = self
+                .symtabs
+                .get(&(sh.sh_link as usize))
+                .ok_or(SectionLinkOutOfBounds(idx))?
+                .len();
+            let dstsize = self
+                .shdrs
+                .get(sh.sh_info as usize)
+                .ok_or(SectionInfoOutOfBounds(idx))?
+                .sh_size;
+            let relatab = self.read_section_table::<Rela>(idx)?;
+            relatab.iter().enumerate().try_for_each(|(relaidx, r)| {
+                if r.symbol_offset() >= nsyms {
+                    return Err(RelaSymbolOutOfBounds(relaidx));
+                }
+                r.r_offset
+                    .checked_add(r.patch_size())
+                    .filter(|&patchend| patchend <= dstsize)
+                    .ok_or(RelaOffsetOutOfBounds(relaidx))
+                    .map(|_| ())
+            })?;
+            self.relatabs.insert(idx, relatab);
+            Ok(())
+        })
+    }
+
+    fn read_section_table<T: Clone>(&self, idx: usize) -> Result<Cow<'a, [T]>, ELFFileError> {
+        let sh = &self.section_headers()[idx];
+        if sh.sh_entsize != mem::size_of::<T>() || sh.sh_size % sh.sh_entsize != 0 {
+            return Err(ELFFileError::SectionEntSizeInvalid(idx));
         }
-        Ok(elf_file)
+        self.source.read_table(
+            sh.sh_offset,
+            sh.sh_size / sh.sh_entsize,
+            ELFFileError::SectionOutOfBounds(idx),
+        )
+    }
+
+    fn load_strtab(&mut self, strndx: usize) -> Result<(), ELFFileError> {
+        if !self.strtabs.contains_key(&strndx) {
+            let sh = &self.section_headers()[strndx];
+            let strtab = self.source.read_table(
+                sh.sh_offset,
+                sh.sh_size,
+                ELFFileError::SectionOutOfBounds(strndx),
+            )?;
+            self.strtabs.insert(strndx, strtab);
+        }
+        Ok(())
     }
 
-    pub fn get_undefined_symbol_names(&self) -> Vec<&'static str> {
+    /* NUL-terminated string at `offset` of string table section `strndx` */
+    fn string_at(&self, strndx: usize, offset: usize) -> Result<&str, ELFFileError> {
+        use self::ELFFileError::*;
+        let strtab = self.strtabs.get(&strndx).ok_or(StringOutOfBounds(offset))?;
+        let bytes = strtab.get(offset..).ok_or(StringOutOfBounds(offset))?;
+        let strlen = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(StringNotValid(offset))?;
+        core::str::from_utf8(&bytes[..strlen]).map_err(|_| StringNotValid(offset))
+    }
+
+    /* copy a section straight into its loaded place, bounds are checked in parse */
+    pub fn read_section(&self, sh: &SHeader, dst: &mut [u8]) -> Result<(), ELFFileError> {
+        match sh.section_type() {
+            SHType::NOBITS => Ok(dst.fill(0)),
+            _ => self.source.read_at(sh.sh_offset, &mut dst[..sh.sh_size]),
+        }
+    }
+
+    /* names are checked in parse, so an empty name is never returned by mistake */
+    pub fn section_name(&self, sh: &SHeader) -> &str {
+        match self.elf_header().e_shstrndx as usize {
+            0 => "",
+            shstrndx => self
+                .string_at(shstrndx, sh.sh_name as usize)
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn symbol_name(&self, symtab: &SHeader, sym: &Symbol) -> &str {
+        self.string_at(symtab.sh_link as usize, sym.st_name as usize)
+            .unwrap_or_default()
+    }
+
+    /* tables are loaded in parse, keyed by section index */
+    pub fn symbols(&self, symndx: usize) -> &[Symbol] {
+        self.symtabs.get(&symndx).map_or(&[], |symbols| symbols)
+    }
+
+    pub fn relas(&self, relandx: usize) -> &[Rela] {
+        self.relatabs.get(&relandx).map_or(&[], |relas| relas)
+    }
+
+    pub fn get_undefined_symbols(&self) -> Vec<(&str, &Symbol)> {
         self.section_headers()
             .iter()
-            .filter(|&sh| matches!(sh.sh_type, SHType::SYMTAB))
-            .flat_map(|sh| {
-                let strtab = &self.section_headers()[sh.sh_link as usize];
-                let symbols = unsafe {
-                    core::slice::from_raw_parts_mut(
-                        self.start_address().offset(sh.sh_offset as isize) as *mut Symbol,
-                        sh.sh_size / sh.sh_entsize,
-                    )
-                };
-                symbols
-                    .iter_mut()
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::SYMTAB))
+            .flat_map(|(idx, sh)| {
+                self.symbols(idx)
+                    .iter()
                     /* SHN_UNDEF with name */
                     .filter(|s| s.st_shndx == 0 && s.st_name != 0)
-                    .map(|s| {
-                        let sym_name = unsafe {
-                            crate::cstr2ruststr(
-                                self.start_address()
-                                    .offset(strtab.sh_offset as isize)
-                                    .offset(s.st_name as isize),
-                            )
-                        };
+                    .map(move |s| {
+                        let sym_name = self.symbol_name(sh, s);
                         // println!("SHN_UNDEF: name={} {:#x}", sym_name, s.st_value);
-                        sym_name
+                        (sym_name, s)
                     })
             })
             .collect()
     }
 
-    pub fn get_all_symbol_names(&self) -> Vec<&'static str> {
+    pub fn get_defined_symbols(&self) -> Vec<(&str, &Symbol)> {
         self.section_headers()
             .iter()
-            .filter(|&sh| matches!(sh.sh_type, SHType::SYMTAB))
-            .flat_map(|sh| {
-                let strtab = &self.section_headers()[sh.sh_link as usize];
-                let symbols = unsafe {
-                    core::slice::from_raw_parts_mut(
-                        self.start_address().offset(sh.sh_offset as isize) as *mut Symbol,
-                        sh.sh_size / sh.sh_entsize,
-                    )
-                };
-                symbols
-                    .iter_mut()
-                    /* SHN_UNDEF with name */
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::SYMTAB))
+            .flat_map(|(idx, sh)| {
+                self.symbols(idx)
+                    .iter()
+                    /* defined with name */
                     .filter(|s| s.st_shndx != 0 && s.st_name != 0)
-                    .map(|s| {
-                        let sym_name = unsafe {
-                            crate::cstr2ruststr(
-                                self.start_address()
-                                    .offset(strtab.sh_offset as isize)
-                                    .offset(s.st_name as isize),
-                            )
-                        };
-                        sym_name
-                    })
+                    .map(move |s| (self.symbol_name(sh, s), s))
             })
             .collect()
     }
 
-    pub fn calculate_needed_size(&self) -> ((usize, usize), (usize, usize)) {
-        /* get needed size for allocation */
+    /* SHN_COMMON, st_value is alignment and st_size is size */
+    pub fn get_common_symbols(&self) -> Vec<(&str, &Symbol)> {
+        self.get_defined_symbols()
+            .into_iter()
+            .filter(|(_, s)| s.st_shndx == 0xfff2)
+            .collect()
+    }
+
+    /* defined GLOBAL FUNC/OBJECT symbols of an executable with their addresses */
+    pub fn get_exported_symbols(&self) -> Vec<(&str, usize)> {
         self.section_headers()
             .iter()
-            /* section has alloc flag need to be load */
-            .filter(|&sh| sh.sh_flags & (SHFlags::ALLOC as usize) != 0)
-            /* calculate the required size and align */
-            .fold(((0, 0), (0, 0)), |td, sh| {
-                // println!("{:?}", tdalign);
-                fn alignup(v: usize, a: usize) -> usize {
-                    (v as *const u8).align_offset(a) + v
-                }
-
-                let sec_align = sh.sh_addralign;
-                let sec_size = alignup(sh.sh_size, 4);
-                match sh.sh_flags & (SHFlags::WRITE as usize) {
-                    /* text/rodata section has not write flag */
-                    0 => (
-                        (
-                            alignup(td.0 .0, sec_align) + sec_size,
-                            td.0 .1.max(sec_align),
-                        ),
-                        td.1,
-                    ),
-                    /* data/bss section has write flag */
-                    _ => (
-                        td.0,
-                        (
-                            alignup(td.1 .0, sec_align) + sec_size,
-                            td.1 .1.max(sec_align),
-                        ),
-                    ),
-                }
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::SYMTAB))
+            .flat_map(|(idx, sh)| {
+                self.symbols(idx)
+                    .iter()
+                    .filter(|s| s.st_shndx != 0 && s.st_name != 0)
+                    .filter(|s| matches!(s.symbol_bind(), STBind::GLOBAL))
+                    .filter(|s| matches!(s.symbol_type(), STType::FUNC | STType::OBJECT))
+                    .map(move |s| (self.symbol_name(sh, s), s.st_value))
             })
+            .collect()
     }
 
-    pub const fn elf_header(&self) -> &EHeader {
-        /* it's safe because we succeed to parse it */
-        unsafe { &*self.ehaddr }
+    pub fn elf_header(&self) -> &EHeader {
+        &self.ehdr
     }
 
-    pub const fn section_headers(&self) -> &[SHeader] {
-        /* it's safe because we succeed to parse it */
-        unsafe { &*self.shaddr }
+    pub fn section_headers(&self) -> &[SHeader] {
+        &self.shdrs
     }
+}
 
-    pub const fn start_address(&self) -> *const u8 {
-        self.ehaddr as *const u8
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn shdr(sh_type: u32, sh_offset: usize, sh_size: usize, sh_link: u32) -> SHeader {
+        SHeader {
+            sh_name: 0,
+            sh_type,
+            sh_flags: 0,
+            sh_addr: 0,
+            sh_offset,
+            sh_size,
+            sh_link,
+            sh_info: 0,
+            sh_addralign: 1,
+            sh_entsize: match sh_type {
+                2 => mem::size_of::<Symbol>(),
+                _ => 0,
+            },
+        }
     }
 
-    pub unsafe fn relocateadd(rela: &Rela, sym: &Symbol, addr: *mut u8) {
-        use RelaType::*;
-        let rtype = &rela.rela_type();
-        print!(
-            "{:?} @{:p} [{:08x}] to sym@{:p}",
-            rtype,
-            addr,
-            (addr as *mut u32).read_unaligned(),
-            sym.st_value as *const u32,
-        );
-        match rtype {
-            RELAX => {}
-            RISCV_32 => {
-                (addr as *mut u32).write_unaligned(
-                    (sym.symbol_value() as *const u8).wrapping_offset(rela.rela_addend()) as u32,
-                );
-                println!("");
-            }
-            RISCV_64 => {
-                (addr as *mut u64).write_unaligned(
-                    (sym.symbol_value() as *const u8).wrapping_offset(rela.rela_addend()) as u64,
-                );
-                println!("");
-            }
-            PCREL_LO12_I | PCREL_LO12_S => {
-                println!("              ^")
-                /* NOTE: imm value for mv has been adjusted in previous HI20 */
-            }
-            PCREL_HI20 | CALL | CALL_PLT => {
-                let offset =
-                    (sym.symbol_value() as *const u8).offset_from(addr as *const u8) as usize;
-                let hi20 = offset.wrapping_add(0x800) & 0xfffff000;
-                let lo12 = offset.wrapping_sub(hi20) & 0xfff;
-                /* Adjust auipc (add upper immediate to pc) : 20bit */
-                (addr as *mut u32)
-                    .write_unaligned(((addr as *mut u32).read_unaligned() & 0xfff) | (hi20 as u32));
-                println!(" [{:08x}][{:08x}]", (addr as *mut u32).read_unaligned(),(addr as *mut u32).offset(1).read_unaligned());
-                /* Adjust remain 12bit */
-                match (addr as *mut u32).offset(1).read_unaligned() & 0x7f {
-                    // OPCODE_SW       0x23
-                    // OPCODE_LUI      0x37
-                    // RVI_OPCODE_MASK 0x7F
-                    0x23 => {
-                        /* Adjust imm for SW : S-type */
-                        let imm11_5 = ((lo12 & 0xfe0) << (31 - 11)) as u32;
-                        let imm4_0 = ((lo12 & 0x1f) << (11 - 4)) as u32;
-                        (addr as *mut u32).offset(1).write_unaligned(
-                            ((addr as *mut u32).offset(1).read_unaligned() & 0x1fff07f)
-                                | imm11_5
-                                | imm4_0,
-                        );
-                    }
-                    _ => {
-                        /* Adjust imm for MV(ADDI)/JALR : I-type */
-                        (addr as *mut u32).offset(1).write_unaligned(
-                            ((addr as *mut u32).offset(1).read_unaligned() & 0xfffff)
-                                | ((lo12 << 20) as u32),
-                        );
-                    }
-                }
-            }
-            BRANCH => {
-                let offset = (sym.symbol_value() as *const u8).offset_from(addr as *const u8) as usize;
-                /* P.23 Conditinal Branches : B type (imm=12bit) 0xfe000f80 */
-                let val = (addr as *mut u32).read_unaligned() & 0xfe000f80;
-                let imm12 = (offset as u32 & 0x1000) << (31 - 12);
-                let imm11 = (offset as u32 & 0x800) >> (11 - 7);
-                let imm10_5 = (offset as u32 & 0x7e0) << (30 - 10);
-                let imm4_1 = (offset as u32 & 0x1e) << (11 - 4);
-
-                assert_eq!(val, imm12 | imm11 | imm10_5 | imm4_1);
-                
-                println!(" [{:08x}]", (addr as *mut u32).read_unaligned());
-                println!(
-                    "\toffset for Bx={} ({:#x}) (val={:#x})already set!",
-                    offset, offset, val
-                );
-            }
-            HI20 => {
-                let hi20 = (sym.symbol_value().wrapping_add(0x800) >> 12) << 12;
-                (addr as *mut u32)
-                    .write_unaligned(((addr as *mut u32).read_unaligned() & 0xfff) | (hi20 as u32));
-                println!(" [{:08x}]", (addr as *mut u32).read_unaligned());
-            }
-            LO12_I => {
-                let hi20 = sym.symbol_value().wrapping_add(0x800) & 0xfffff000;
-                let lo12 = sym.symbol_value().wrapping_sub(hi20) & 0xfff;
-                (addr as *mut u32).write_unaligned(
-                    ((addr as *mut u32).read_unaligned() & 0xfffff) | ((lo12 << 20) as u32),
-                );
-                println!(" [{:08x}]", (addr as *mut u32).read_unaligned());
-            }
-            LO12_S => {
-                let hi20 = sym.symbol_value().wrapping_add(0x800) & 0xfffff000;
-                let lo12 = sym.symbol_value().wrapping_sub(hi20) & 0xfff;
-                /* Adjust imm for SW : S-type */
-                let imm11_5 = ((lo12 & 0xfe0) << (31 - 11)) as u32;
-                let imm4_0 = ((lo12 & 0x1f) << (11 - 4)) as u32;
-                (addr as *mut u32).write_unaligned(
-                    ((addr as *mut u32).read_unaligned() & 0x1fff07f) | imm11_5 | imm4_0,
-                );
-                println!(" [{:08x}]", (addr as *mut u32).read_unaligned());
-            }
-            RVC_JUMP => {
-                let offset = (sym.symbol_value() as *const u8).offset_from(addr as *const u8) as usize;
-                /* P.111 Table 16.6 : Instruction listings for RVC 0x1ffc */
-                let val = (addr as *mut u16).read_unaligned() & 0x1ffc;
-                let imm11 = (offset as u16 & 0x800) << (12 - 11);
-                let imm10 = (offset as u16 & 0x400) >> (10 - 8);
-                let imm9_8 = (offset as u16 & 0x300) << (12 - 11);
-                let imm7 = (offset as u16 & 0x80) >> (7 - 6);
-                let imm6 = (offset as u16 & 0x40) << (12 - 11);
-                let imm5 = (offset as u16 & 0x20) >> (5 - 2);
-                let imm4 = (offset as u16 & 0x10) << (12 - 5);
-                let imm3_1 = (offset as u16 & 0xe) << (12 - 10);
-
-                assert_eq!(
-                    val,
-                    imm11 | imm10 | imm9_8 | imm7 | imm6 | imm5 | imm4 | imm3_1
-                );
-
-                println!(" [{:04x}]", (addr as *mut u16).read_unaligned());
-                println!(
-                    "\toffset for C.J={} ({:#x}) (val={:#x})already set!",
-                    offset, offset, val
-                );
-            }
-            RVC_BRANCH => {
-                let offset = (sym.symbol_value() as *const u8).offset_from(addr as *const u8) as usize;
-                /* P.111 Table 16.6 : Instruction listings for RVC 0x1c7c */
-                let val = (addr as *mut u16).read_unaligned() & 0x1c7c;
-                let imm8 = (offset as u16 & 0x100) << (12 - 8);
-                let imm7_6 = (offset as u16 & 0xc0) >> (6 - 5);
-                let imm5 = (offset as u16 & 0x20) >> (5 - 2);
-                let imm4_3 = (offset as u16 & 0x18) << (12 - 5);
-                let imm2_1 = (offset as u16 & 0x6) << (12 - 10);
-
-                assert_eq!(val, imm8 | imm7_6 | imm5 | imm4_3 | imm2_1);
-
-                println!(" [{:04x}]", (addr as *mut u16).read_unaligned());
-                println!(
-                    "\toffset for C.Bx={} ({:#x}) (val={:#x})already set!",
-                    offset, offset, val
-                );
-            }
+    fn symbol(st_shndx: u16, st_value: usize) -> Symbol {
+        Symbol {
+            st_name: 0,
+            st_info: 0,
+            st_other: 0,
+            st_shndx,
+            st_value,
+            st_size: 0,
         }
     }
+
+    /* null, strtab and symtab, identity is skipped as only riscv is accepted */
+    fn validate_symbols(syms: &[Symbol]) -> Result<(), ELFFileError> {
+        let symsize = syms.len() * mem::size_of::<Symbol>();
+        let mut image = vec![0u8; 8 + symsize];
+        image[8..]
+            .copy_from_slice(unsafe { slice::from_raw_parts(syms.as_ptr() as *const u8, symsize) });
+        let mut elf_file = ELFFile {
+            source: ElfSource::Bytes(&image),
+            ehdr: unsafe { mem::zeroed() },
+            shdrs: Cow::Owned(vec![
+                shdr(0, 0, 0, 0),
+                shdr(3, 0, 1, 0),
+                shdr(2, 8, symsize, 1),
+            ]),
+            strtabs: BTreeMap::new(),
+            symtabs: BTreeMap::new(),
+            relatabs: BTreeMap::new(),
+        };
+        elf_file.validate(true)
+    }
+
+    #[test]
+    fn parse_rejects_null_and_short_image() {
+        assert!(matches!(
+            ELFFile::parse(ptr::null(), 0),
+            Err(ELFFileError::FileNotFound)
+        ));
+        let image = [0x7fu8, b'E', b'L', b'F'];
+        assert!(matches!(
+            ELFFile::parse(image.as_ptr(), image.len()),
+            Err(ELFFileError::FileTooSmall)
+        ));
+    }
+
+    #[test]
+    fn validate_accepts_sections_abs_and_common() {
+        let syms = [
+            symbol(0, 0),
+            symbol(1, 0),
+            symbol(0xfff1, 0x1234),
+            symbol(0xfff2, 8),
+        ];
+        assert!(validate_symbols(&syms).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_symbol_section_out_of_bounds() {
+        assert!(matches!(
+            validate_symbols(&[symbol(0, 0), symbol(3, 0)]),
+            Err(ELFFileError::SymbolSectionOutOfBounds(1))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_other_reserved_indices() {
+        [0xff00, 0xff1f, 0xfff0, 0xffff].iter().for_each(|&ndx| {
+            assert!(matches!(
+                validate_symbols(&[symbol(0, 0), symbol(ndx, 0)]),
+                Err(ELFFileError::SymbolSectionOutOfBounds(1))
+            ))
+        });
+    }
+
+    #[test]
+    fn validate_rejects_symbol_value_out_of_section() {
+        /* strtab is 1 byte */
+        assert!(validate_symbols(&[symbol(0, 0), symbol(1, 1)]).is_ok());
+        assert!(matches!(
+            validate_symbols(&[symbol(0, 0), symbol(1, 2)]),
+            Err(ELFFileError::SymbolValueOutOfBounds(1))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_common_align() {
+        assert!(matches!(
+            validate_symbols(&[symbol(0, 0), symbol(0xfff2, 3)]),
+            Err(ELFFileError::CommonAlignInvalid(1))
+        ));
+    }
+
+    #[test]
+    fn validate_rejects_section_out_of_image() {
+        let image = [0u8; 8];
+        let mut elf_file = ELFFile {
+            source: ElfSource::Bytes(&image),
+            ehdr: unsafe { mem::zeroed() },
+            shdrs: Cow::Owned(vec![shdr(0, 0, 0, 0), shdr(3, 4, usize::MAX, 0)]),
+            strtabs: BTreeMap::new(),
+            symtabs: BTreeMap::new(),
+            relatabs: BTreeMap::new(),
+        };
+        assert!(matches!(
+            elf_file.validate(true),
+            Err(ELFFileError::SectionOutOfBounds(1))
+        ));
+    }
 }
@@ -1,36 +1,37 @@
 mod e_header;
 mod s_header;
 
-use e_header::{EIdent, EMachine, EType, EVersion};
+use e_header::EIdent;
 pub use s_header::{SHFlags, SHType};
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct EHeader {
     /* byte 0-15 */
     pub e_ident: EIdent, /* Magic number and other info */
     /* byte 16-17 */
-    pub e_type: EType, /* Object file type */
+    pub e_type: u16, /* Object file type */
     /* byte 18-19 */
-    pub e_machine: EMachine, /* Architecture */
+    pub e_machine: u16, /* Architecture */
     /* byte 19-22 */
-    pub e_version: EVersion, /* Object file version */
-    pub e_entry: usize,      /* Entry point virtual address */
-    pub e_phoff: usize,      /* Program header table file offset */
-    pub e_shoff: usize,      /* Section header table file offset */
-    pub e_flags: u32,        /* Processor-specific flags */
-    pub e_ehsize: u16,       /* ELF header size in bytes */
-    pub e_phentsize: u16,    /* Program header table entry size */
-    pub e_phnum: u16,        /* Program header table entry count */
-    pub e_shentsize: u16,    /* Section header table entry size */
-    pub e_shnum: u16,        /* Section header table entry count */
-    pub e_shstrndx: u16,     /* Section header string table index */
+    pub e_version: u32,   /* Object file version */
+    pub e_entry: usize,   /* Entry point virtual address */
+    pub e_phoff: usize,   /* Program header table file offset */
+    pub e_shoff: usize,   /* Section header table file offset */
+    pub e_flags: u32,     /* Processor-specific flags */
+    pub e_ehsize: u16,    /* ELF header size in bytes */
+    pub e_phentsize: u16, /* Program header table entry size */
+    pub e_phnum: u16,     /* Program header table entry count */
+    pub e_shentsize: u16, /* Section header table entry size */
+    pub e_shnum: u16,     /* Section header table entry count */
+    pub e_shstrndx: u16,  /* Section header string table index */
 }
 
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SHeader {
     pub sh_name: u32,
-    pub sh_type: SHType,
+    pub sh_type: u32,
     pub sh_flags: usize,
     pub sh_addr: usize,
     pub sh_offset: usize,
@@ -1,3 +1,4 @@
+mod e_flags;
 mod e_ident;
 mod e_machine;
 mod e_type;
@@ -5,20 +6,66 @@ mod e_version;
 
 use core::fmt::Debug;
 
+pub use e_flags::*;
 pub use e_ident::EIdent;
 pub use e_machine::EMachine;
 pub use e_type::EType;
 pub use e_version::EVersion;
 
 use super::EHeader;
+use crate::elf::ELFFileError;
 
 impl EHeader {
-    pub const fn is_valid(&self) -> bool {
-        self.e_ident.is_valid()
-            && match self.e_version {
-                EVersion::CURRENT => true,
-                _ => false,
+    pub fn elf_type(&self) -> EType {
+        EType::try_from(self.e_type).unwrap_or_else(EType::Unknown)
+    }
+
+    pub fn machine(&self) -> EMachine {
+        EMachine::try_from(self.e_machine).unwrap_or_else(EMachine::Unknown)
+    }
+
+    pub fn version(&self) -> EVersion {
+        EVersion::try_from(self.e_version).unwrap_or_else(EVersion::Unknown)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.e_ident.is_valid() && matches!(self.version(), EVersion::CURRENT)
+    }
+
+    /* reject what can't be loaded on the running target, or isn't the firmware of it */
+    pub fn check_identity(&self, relocatable: bool) -> Result<(), ELFFileError> {
+        use ELFFileError::*;
+        if !self.e_ident.is_native_class() {
+            return Err(ClassMismatch);
+        }
+        if !self.e_ident.is_native_data() {
+            return Err(EndianMismatch);
+        }
+        match (relocatable, self.elf_type()) {
+            (true, EType::REL) | (false, EType::EXEC | EType::DYN) => {}
+            (true, _) => return Err(TypeNotRelocatable),
+            (false, _) => return Err(TypeNotExecutable),
+        }
+        match self.machine() {
+            #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+            EMachine::RISCV => {
+                if self.float_abi() != HOST_FLOAT_ABI {
+                    return Err(FloatAbiMismatch(self.float_abi()));
+                }
+                if (self.e_flags & EF_RISCV_RVE != 0) != HOST_RVE {
+                    return Err(RveMismatch);
+                }
+                if self.e_flags & EF_RISCV_RVC != 0 && !HOST_RVC {
+                    return Err(RvcNotSupported);
+                }
+                Ok(())
             }
+            _ => Err(MachineMismatch),
+        }
+    }
+
+    pub const fn float_abi(&self) -> u32 {
+        self.e_flags & EF_RISCV_FLOAT_ABI
     }
 
     pub const fn elf_flags(&self) -> u32 {
@@ -42,9 +89,9 @@ impl Debug for EHeader {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("EHeader")
             .field("ident", &self.e_ident)
-            .field("type", &self.e_type)
-            .field("machine", &self.e_machine)
-            .field("version", &self.e_version)
+            .field("type", &self.elf_type())
+            .field("machine", &self.machine())
+            .field("version", &self.version())
             .field("entry", &self.e_entry)
             .field("phoff", &self.e_phoff)
             .field("shoff", &self.e_shoff)
@@ -0,0 +1,23 @@
+/* RISC-V specific e_flags */
+pub const EF_RISCV_RVC: u32 = 0x0001;
+pub const EF_RISCV_FLOAT_ABI: u32 = 0x0006;
+pub const EF_RISCV_FLOAT_ABI_SOFT: u32 = 0x0000;
+pub const EF_RISCV_FLOAT_ABI_SINGLE: u32 = 0x0002;
+pub const EF_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x0004;
+pub const EF_RISCV_FLOAT_ABI_QUAD: u32 = 0x0006;
+pub const EF_RISCV_RVE: u32 = 0x0008;
+pub const EF_RISCV_TSO: u32 = 0x0010;
+
+/* float abi the host is built with, riscv32imafc is ilp32f */
+#[cfg(target_feature = "d")]
+pub const HOST_FLOAT_ABI: u32 = EF_RISCV_FLOAT_ABI_DOUBLE;
+#[cfg(all(target_feature = "f", not(target_feature = "d")))]
+pub const HOST_FLOAT_ABI: u32 = EF_RISCV_FLOAT_ABI_SINGLE;
+#[cfg(not(target_feature = "f"))]
+pub const HOST_FLOAT_ABI: u32 = EF_RISCV_FLOAT_ABI_SOFT;
+
+/* ilp32e code has another calling convention and stack alignment than the host */
+pub const HOST_RVE: bool = cfg!(target_feature = "e");
+
+/* compressed code can only run on host with C extension */
+pub const HOST_RVC: bool = cfg!(target_feature = "c");
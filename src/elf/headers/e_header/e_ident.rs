@@ -11,15 +11,16 @@ use self::ei_magic::EIMagic;
 use self::ei_version::EIVersion;
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct EIdent {
     /* byte 0-3 */
     pub magic: EIMagic,
     /* byte 4 */
-    pub class: EIClass,
+    pub class: u8,
     /* byte 5 */
-    pub data: EIData,
+    pub data: u8,
     /* byte 6 */
-    pub version: EIVersion,
+    pub version: u8,
     /* byte 7 */
     pub osabi: u8,
     /* byte 8 */
@@ -29,12 +30,40 @@ pub struct EIdent {
 }
 
 impl EIdent {
-    pub const fn is_valid(&self) -> bool {
-        self.magic.is_valid()
-            && match self.version {
-                EIVersion::CURRENT => true,
-                _ => false,
-            }
+    pub fn class(&self) -> EIClass {
+        EIClass::try_from(self.class).unwrap_or_else(EIClass::Unknown)
+    }
+
+    pub fn data(&self) -> EIData {
+        EIData::try_from(self.data).unwrap_or_else(EIData::Unknown)
+    }
+
+    pub fn version(&self) -> EIVersion {
+        EIVersion::try_from(self.version).unwrap_or_else(EIVersion::Unknown)
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.magic.is_valid() && matches!(self.version(), EIVersion::CURRENT)
+    }
+
+    pub fn is_native_class(&self) -> bool {
+        match self.class() {
+            #[cfg(target_pointer_width = "32")]
+            EIClass::CLASS32 => true,
+            #[cfg(target_pointer_width = "64")]
+            EIClass::CLASS64 => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_native_data(&self) -> bool {
+        match self.data() {
+            #[cfg(target_endian = "little")]
+            EIData::LSB => true,
+            #[cfg(target_endian = "big")]
+            EIData::MSB => true,
+            _ => false,
+        }
     }
 }
 
@@ -42,9 +71,9 @@ impl Debug for EIdent {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("")
             .field("magic", &self.magic)
-            .field("class", &self.class)
-            .field("data", &self.data)
-            .field("version", &self.version)
+            .field("class", &self.class())
+            .field("data", &self.data())
+            .field("version", &self.version())
             .field("osabi", &self.osabi)
             .field("abiversion", &self.abiversion)
             .finish()
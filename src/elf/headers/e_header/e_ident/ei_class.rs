@@ -1,7 +1,21 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EIClass {
-    CLASSNONE = 0,
-    CLASS32 = 1,
-    CLASS64 = 2,
+    CLASSNONE,
+    CLASS32,
+    CLASS64,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for EIClass {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use EIClass::*;
+        match v {
+            0 => Ok(CLASSNONE),
+            1 => Ok(CLASS32),
+            2 => Ok(CLASS64),
+            _ => Err(v),
+        }
+    }
 }
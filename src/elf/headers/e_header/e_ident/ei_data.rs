@@ -1,7 +1,21 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EIData {
-    NONE = 0,
-    LSB = 1,
-    MSB = 2,
+    NONE,
+    LSB,
+    MSB,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for EIData {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use EIData::*;
+        match v {
+            0 => Ok(NONE),
+            1 => Ok(LSB),
+            2 => Ok(MSB),
+            _ => Err(v),
+        }
+    }
 }
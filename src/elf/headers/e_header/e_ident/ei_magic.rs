@@ -1,6 +1,7 @@
 use core::fmt::Debug;
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct EIMagic([u8; 4]);
 
 impl EIMagic {
@@ -1,6 +1,19 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EIVersion {
-    NONE = 0,
-    CURRENT = 1,
+    NONE,
+    CURRENT,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for EIVersion {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use EIVersion::*;
+        match v {
+            0 => Ok(NONE),
+            1 => Ok(CURRENT),
+            _ => Err(v),
+        }
+    }
 }
@@ -1,8 +1,19 @@
-#[repr(u16)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EMachine {
-    NONE = 0,
-    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
-    RISCV = 243,
-    //todo!();
+    NONE,
+    RISCV,
+    Unknown(u16),
+}
+
+impl TryFrom<u16> for EMachine {
+    type Error = u16;
+
+    fn try_from(v: u16) -> Result<Self, Self::Error> {
+        use EMachine::*;
+        match v {
+            0 => Ok(NONE),
+            243 => Ok(RISCV),
+            _ => Err(v),
+        }
+    }
 }
@@ -1,13 +1,33 @@
-#[repr(u16)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EType {
-    NONE = 0,        /* No file type */
-    REL = 1,         /* Relocatable file */
-    EXEC = 2,        /* Executable file */
-    DYN = 3,         /* Shared object file */
-    CORE = 4,        /* Core file */
-    LOOS = 0xfe00,   /* Operating system-specific */
-    HIOS = 0xfeff,   /* Operating system-specific */
-    LOPROC = 0xff00, /* Processor-specific */
-    HIPROC = 0xffff, /* Processor-specific */
+    NONE,   /* No file type */
+    REL,    /* Relocatable file */
+    EXEC,   /* Executable file */
+    DYN,    /* Shared object file */
+    CORE,   /* Core file */
+    LOOS,   /* Operating system-specific */
+    HIOS,   /* Operating system-specific */
+    LOPROC, /* Processor-specific */
+    HIPROC, /* Processor-specific */
+    Unknown(u16),
+}
+
+impl TryFrom<u16> for EType {
+    type Error = u16;
+
+    fn try_from(v: u16) -> Result<Self, Self::Error> {
+        use EType::*;
+        match v {
+            0 => Ok(NONE),
+            1 => Ok(REL),
+            2 => Ok(EXEC),
+            3 => Ok(DYN),
+            4 => Ok(CORE),
+            0xfe00 => Ok(LOOS),
+            0xfeff => Ok(HIOS),
+            0xff00 => Ok(LOPROC),
+            0xffff => Ok(HIPROC),
+            _ => Err(v),
+        }
+    }
 }
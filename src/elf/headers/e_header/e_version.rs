@@ -1,6 +1,19 @@
-#[repr(u32)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EVersion {
-    NONE = 0,
-    CURRENT = 1,
+    NONE,
+    CURRENT,
+    Unknown(u32),
+}
+
+impl TryFrom<u32> for EVersion {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, Self::Error> {
+        use EVersion::*;
+        match v {
+            0 => Ok(NONE),
+            1 => Ok(CURRENT),
+            _ => Err(v),
+        }
+    }
 }
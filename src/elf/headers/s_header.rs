@@ -11,8 +11,8 @@ impl SHeader {
         self.sh_name as usize
     }
 
-    pub const fn section_type(&self) -> &SHType {
-        &self.sh_type
+    pub fn section_type(&self) -> SHType {
+        SHType::try_from(self.sh_type).unwrap_or_else(SHType::Unknown)
     }
 
     pub const fn section_flags(&self) -> usize {
@@ -46,11 +46,4 @@ impl SHeader {
     pub const fn section_address(&self) -> usize {
         self.sh_addr
     }
-
-    pub fn section_address_set(&self, addr: usize) {
-        let paddr = core::ptr::addr_of!(self.sh_addr);
-        unsafe {
-            core::ptr::write_unaligned(paddr as *mut usize, addr);
-        }
-    }
 }
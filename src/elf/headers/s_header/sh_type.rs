@@ -1,22 +1,64 @@
-#[repr(u32)]
-#[derive(Debug)]
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SHType {
-    NULL = 0,
-    PROGBITS = 1,
-    SYMTAB = 2,
-    STRTAB = 3,
-    RELA = 4,
-    HASH = 5,
-    DYNAMIC = 6,
-    NOTE = 7,
-    NOBITS = 8,
-    REL = 9,
-    SHLIB = 10,
-    DYNSYM = 11,
-    LOOS = 0x60000000,
-    HIOS = 0x6fffffff,
-    LOPROC = 0x70000000,
-    HIPROC = 0x7fffffff,
-    LOUSER = 0x80000000,
-    HIUSER = 0xffffffff,
+    NULL,
+    PROGBITS,
+    SYMTAB,
+    STRTAB,
+    RELA,
+    HASH,
+    DYNAMIC,
+    NOTE,
+    NOBITS,
+    REL,
+    SHLIB,
+    DYNSYM,
+    INIT_ARRAY,
+    FINI_ARRAY,
+    PREINIT_ARRAY,
+    GROUP,
+    SYMTAB_SHNDX,
+    LOOS,
+    HIOS,
+    LOPROC,
+    RISCV_ATTRIBUTES,
+    HIPROC,
+    LOUSER,
+    HIUSER,
+    Unknown(u32),
+}
+
+impl TryFrom<u32> for SHType {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, Self::Error> {
+        use SHType::*;
+        match v {
+            0 => Ok(NULL),
+            1 => Ok(PROGBITS),
+            2 => Ok(SYMTAB),
+            3 => Ok(STRTAB),
+            4 => Ok(RELA),
+            5 => Ok(HASH),
+            6 => Ok(DYNAMIC),
+            7 => Ok(NOTE),
+            8 => Ok(NOBITS),
+            9 => Ok(REL),
+            10 => Ok(SHLIB),
+            11 => Ok(DYNSYM),
+            14 => Ok(INIT_ARRAY),
+            15 => Ok(FINI_ARRAY),
+            16 => Ok(PREINIT_ARRAY),
+            17 => Ok(GROUP),
+            18 => Ok(SYMTAB_SHNDX),
+            0x60000000 => Ok(LOOS),
+            0x6fffffff => Ok(HIOS),
+            0x70000000 => Ok(LOPROC),
+            0x70000003 => Ok(RISCV_ATTRIBUTES),
+            0x7fffffff => Ok(HIPROC),
+            0x80000000 => Ok(LOUSER),
+            0xffffffff => Ok(HIUSER),
+            _ => Err(v),
+        }
+    }
 }
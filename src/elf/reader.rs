@@ -0,0 +1,11 @@
+use super::ELFFileError;
+
+/* Random access to an ELF image which is not resident in memory,
+ * e.g. SPI flash, SD card or UART. Use interior mutability for state. */
+pub trait ElfRead {
+    /* size of the image, reads beyond it are rejected before `read` */
+    fn size(&self) -> usize;
+
+    /* fill the whole `buf` with bytes of the image from `offset` */
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), ELFFileError>;
+}
@@ -0,0 +1,462 @@
+use alloc::collections::BTreeMap;
+
+use super::section::{Rela, RelaType};
+use super::{ELFFile, RelocateError};
+
+/* I-type imm[11:0] at inst[31:20] */
+const fn itype_imm(v: usize) -> u32 {
+    ((v as u32) & 0xfff) << 20
+}
+
+/* S-type imm[11:5] at inst[31:25], imm[4:0] at inst[11:7] */
+const fn stype_imm(v: usize) -> u32 {
+    let v = v as u32;
+    ((v & 0xfe0) << 20) | ((v & 0x1f) << 7)
+}
+
+/* B-type imm[12|10:5] at inst[31:25], imm[4:1|11] at inst[11:7] */
+const fn btype_imm(v: usize) -> u32 {
+    let v = v as u32;
+    ((v & 0x1000) << 19) | ((v & 0x7e0) << 20) | ((v & 0x1e) << 7) | ((v & 0x800) >> 4)
+}
+
+/* J-type imm[20|10:1|11|19:12] at inst[31:12] */
+const fn jtype_imm(v: usize) -> u32 {
+    let v = v as u32;
+    ((v & 0x100000) << 11) | ((v & 0x7fe) << 20) | ((v & 0x800) << 9) | (v & 0xff000)
+}
+
+/* CB-type offset[8|4:3] at inst[12:10], offset[7:6|2:1|5] at inst[6:2] */
+const fn cbtype_imm(v: usize) -> u16 {
+    let v = v as u16;
+    ((v & 0x100) << 4)
+        | ((v & 0x18) << 7)
+        | ((v & 0xc0) >> 1)
+        | ((v & 0x6) << 2)
+        | ((v & 0x20) >> 3)
+}
+
+/* CJ-type offset[11|4|9:8|10|6|7|3:1|5] at inst[12:2] */
+const fn cjtype_imm(v: usize) -> u16 {
+    let v = v as u16;
+    ((v & 0x800) << 1)
+        | ((v & 0x10) << 7)
+        | ((v & 0x300) << 1)
+        | ((v & 0x400) >> 2)
+        | ((v & 0x40) << 1)
+        | ((v & 0x80) >> 1)
+        | ((v & 0xe) << 2)
+        | ((v & 0x20) >> 3)
+}
+
+/* C.LUI nzimm[17] at inst[12], nzimm[16:12] at inst[6:2] */
+const fn cilui_imm(v: usize) -> u16 {
+    (((v & 0x20000) >> 5) | ((v & 0x1f000) >> 10)) as u16
+}
+
+/* upper 20bit rounded for a sign-extended lower 12bit */
+const fn hi20(v: usize) -> usize {
+    v.wrapping_add(0x800) & 0xfffff000
+}
+
+const fn lo12(v: usize) -> usize {
+    v.wrapping_sub(hi20(v)) & 0xfff
+}
+
+/* `v` fits a `bits` wide signed immediate and is a multiple of `align` */
+fn check_imm(rtype: RelaType, v: usize, bits: u32, align: usize) -> Result<usize, RelocateError> {
+    let half = 1i64 << (bits - 1);
+    match (-half..half).contains(&(v as isize as i64)) && v % align == 0 {
+        true => Ok(v),
+        false => Err(RelocateError::Overflow(rtype, v)),
+    }
+}
+
+/* `v` is reachable by a hi20 + lo12 pair */
+fn check_hi20(rtype: RelaType, v: usize) -> Result<usize, RelocateError> {
+    check_imm(rtype, v.wrapping_add(0x800), 32, 1).map(|_| v)
+}
+
+unsafe fn patch32(addr: *mut u8, keep: u32, imm: u32) {
+    let p = addr as *mut u32;
+    p.write_unaligned((p.read_unaligned() & keep) | imm)
+}
+
+unsafe fn patch16(addr: *mut u8, keep: u16, imm: u16) {
+    let p = addr as *mut u16;
+    p.write_unaligned((p.read_unaligned() & keep) | imm)
+}
+
+/* rewrite a ULEB128 in place without changing its encoded length,
+ * a value which needs more bytes overflows */
+fn patch_uleb128(
+    rtype: RelaType,
+    place: &mut [u8],
+    f: impl FnOnce(u64) -> u64,
+) -> Result<(), RelocateError> {
+    let len = place
+        .iter()
+        .take(10)
+        .position(|&b| b & 0x80 == 0)
+        .ok_or(RelocateError::Malformed(rtype, place.as_ptr() as usize))?
+        + 1;
+    let old = place[..len]
+        .iter()
+        .rev()
+        .fold(0u64, |v, &b| (v << 7) | (b & 0x7f) as u64);
+    let new = f(old);
+    if new.checked_shr(7 * len as u32).unwrap_or(0) != 0 {
+        return Err(RelocateError::Overflow(rtype, new as usize));
+    }
+    place[..len].iter_mut().enumerate().for_each(|(i, b)| {
+        let more = if i + 1 < len { 0x80 } else { 0 };
+        *b = ((new >> (7 * i)) & 0x7f) as u8 | more;
+    });
+    Ok(())
+}
+
+impl<'a> ELFFile<'a> {
+    /* S + A - P of a PCREL_HI20 or GOT_HI20, used by the PCREL_LO12 pointing to it */
+    pub fn pcrel_hi20_value(rela: &Rela, symval: usize, addr: usize) -> usize {
+        symval
+            .wrapping_add(rela.rela_addend() as usize)
+            .wrapping_sub(addr)
+    }
+
+    /* `place` starts at the relocated address and ends with its section,
+     * `hi20_values` maps address of each auipc to its `pcrel_hi20_value` */
+    pub unsafe fn relocateadd(
+        rela: &Rela,
+        symval: usize,
+        place: &mut [u8],
+        hi20_values: &BTreeMap<usize, usize>,
+    ) -> Result<(), RelocateError> {
+        let rtype = rela.rela_type();
+        print!("{:?} @{:p} to sym@{:#x}", rtype, place.as_ptr(), symval);
+        /* S + A */
+        let value = symval.wrapping_add(rela.rela_addend() as usize);
+        let patched = Self::patch_value(rtype, value, place, hi20_values);
+        println!();
+        patched
+    }
+
+    /* range of each field is checked before patching, nothing is written on overflow */
+    unsafe fn patch_value(
+        rtype: RelaType,
+        value: usize,
+        place: &mut [u8],
+        hi20_values: &BTreeMap<usize, usize>,
+    ) -> Result<(), RelocateError> {
+        use RelaType::*;
+        let addr = place.as_mut_ptr();
+        /* S + A - P */
+        let pcrel = value.wrapping_sub(addr as usize);
+        match rtype {
+            /* hints for linker relaxation and gc, no relaxation is done */
+            NONE | RELAX | GNU_VTINHERIT | GNU_VTENTRY => {}
+            ALIGN => {
+                /* no symbol, `value` is the nop padding in bytes which isn't deleted here,
+                 * so the code after it must already be aligned */
+                let align = value
+                    .checked_add(2)
+                    .and_then(usize::checked_next_power_of_two)
+                    .ok_or(RelocateError::Overflow(rtype, value))?;
+                if (addr as usize).wrapping_add(value) % align != 0 {
+                    return Err(RelocateError::Unsupported(rtype));
+                }
+            }
+            RISCV_32 => (addr as *mut u32).write_unaligned(value as u32),
+            RISCV_64 => (addr as *mut u64).write_unaligned(value as u64),
+            RISCV_32_PCREL | PLT32 => {
+                let pcrel = check_imm(rtype, pcrel, 32, 1)?;
+                (addr as *mut u32).write_unaligned(pcrel as u32)
+            }
+            ADD8 => *addr = (*addr).wrapping_add(value as u8),
+            ADD16 => {
+                let p = addr as *mut u16;
+                p.write_unaligned(p.read_unaligned().wrapping_add(value as u16))
+            }
+            ADD32 => {
+                let p = addr as *mut u32;
+                p.write_unaligned(p.read_unaligned().wrapping_add(value as u32))
+            }
+            ADD64 => {
+                let p = addr as *mut u64;
+                p.write_unaligned(p.read_unaligned().wrapping_add(value as u64))
+            }
+            SUB6 => *addr = (*addr & 0xc0) | ((*addr).wrapping_sub(value as u8) & 0x3f),
+            SUB8 => *addr = (*addr).wrapping_sub(value as u8),
+            SUB16 => {
+                let p = addr as *mut u16;
+                p.write_unaligned(p.read_unaligned().wrapping_sub(value as u16))
+            }
+            SUB32 => {
+                let p = addr as *mut u32;
+                p.write_unaligned(p.read_unaligned().wrapping_sub(value as u32))
+            }
+            SUB64 => {
+                let p = addr as *mut u64;
+                p.write_unaligned(p.read_unaligned().wrapping_sub(value as u64))
+            }
+            SET6 => *addr = (*addr & 0xc0) | (value as u8 & 0x3f),
+            SET8 => *addr = value as u8,
+            SET16 => (addr as *mut u16).write_unaligned(value as u16),
+            SET32 => (addr as *mut u32).write_unaligned(value as u32),
+            SET_ULEB128 => patch_uleb128(rtype, place, |_| value as u64)?,
+            SUB_ULEB128 => patch_uleb128(rtype, place, |v| v.wrapping_sub(value as u64))?,
+            BRANCH => patch32(addr, 0x01fff07f, btype_imm(check_imm(rtype, pcrel, 13, 2)?)),
+            JAL => patch32(addr, 0x00000fff, jtype_imm(check_imm(rtype, pcrel, 21, 2)?)),
+            CALL | CALL_PLT => {
+                /* auipc + jalr */
+                let pcrel = check_hi20(rtype, pcrel)?;
+                patch32(addr, 0xfff, hi20(pcrel) as u32);
+                patch32(addr.add(4), 0xfffff, itype_imm(lo12(pcrel)));
+            }
+            /* Adjust auipc (add upper immediate to pc) : 20bit */
+            PCREL_HI20 | GOT_HI20 => patch32(addr, 0xfff, hi20(check_hi20(rtype, pcrel)?) as u32),
+            PCREL_LO12_I | PCREL_LO12_S => {
+                /* symbol is the label of the paired auipc, possibly in another basic block,
+                 * whose range is checked by itself */
+                let hipcrel = *hi20_values
+                    .get(&value)
+                    .ok_or(RelocateError::PcrelHi20NotFound(value))?;
+                match rtype {
+                    PCREL_LO12_I => patch32(addr, 0xfffff, itype_imm(lo12(hipcrel))),
+                    _ => patch32(addr, 0x01fff07f, stype_imm(lo12(hipcrel))),
+                }
+            }
+            HI20 => patch32(addr, 0xfff, hi20(check_hi20(rtype, value)?) as u32),
+            LO12_I => patch32(addr, 0xfffff, itype_imm(lo12(check_hi20(rtype, value)?))),
+            LO12_S => patch32(addr, 0x01fff07f, stype_imm(lo12(check_hi20(rtype, value)?))),
+            RVC_BRANCH => patch16(addr, 0xe383, cbtype_imm(check_imm(rtype, pcrel, 9, 2)?)),
+            RVC_JUMP => patch16(addr, 0xe003, cjtype_imm(check_imm(rtype, pcrel, 12, 2)?)),
+            RVC_LUI => {
+                /* c.lui takes a non-zero 6bit upper immediate */
+                let value = check_imm(rtype, value.wrapping_add(0x800), 18, 1)
+                    .map(|_| value)
+                    .and_then(|v| match hi20(v) {
+                        0 => Err(RelocateError::Overflow(rtype, v)),
+                        _ => Ok(v),
+                    })?;
+                patch16(addr, 0xef83, cilui_imm(hi20(value)))
+            }
+            /* dynamic, relaxed and tls relocations can't be in a loadable object,
+             * no thread local block is allocated for modules */
+            _ => return Err(RelocateError::Unsupported(rtype)),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::slice;
+
+    fn bytes(insts: &mut [u32]) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(insts.as_mut_ptr() as *mut u8, insts.len() * 4) }
+    }
+
+    fn patch(rtype: RelaType, value: usize, place: &mut [u8]) -> Result<(), RelocateError> {
+        unsafe { ELFFile::patch_value(rtype, value, place, &BTreeMap::new()) }
+    }
+
+    /* `pcrel` from the place, as S + A - P */
+    fn patch_pcrel(rtype: RelaType, pcrel: isize, place: &mut [u8]) -> Result<(), RelocateError> {
+        let value = (place.as_ptr() as usize).wrapping_add(pcrel as usize);
+        patch(rtype, value, place)
+    }
+
+    #[test]
+    fn branch_and_jump_encoding() {
+        /* beq zero, zero, . - 4 */
+        let mut insts = [0x00000063u32];
+        patch_pcrel(RelaType::BRANCH, -4, bytes(&mut insts)).unwrap();
+        assert_eq!(insts[0], 0xfe000ee3);
+        /* j . - 4 */
+        let mut insts = [0x0000006fu32];
+        patch_pcrel(RelaType::JAL, -4, bytes(&mut insts)).unwrap();
+        assert_eq!(insts[0], 0xffdff06f);
+        /* c.j . - 2 */
+        let mut insts = [0x0000a001u32];
+        patch_pcrel(RelaType::RVC_JUMP, -2, &mut bytes(&mut insts)[..2]).unwrap();
+        assert_eq!(insts[0], 0x0000bffd);
+    }
+
+    #[test]
+    fn call_encoding() {
+        /* auipc ra, 0; jalr ra, 0(ra) */
+        let mut insts = [0x00000097u32, 0x000080e7];
+        patch_pcrel(RelaType::CALL, 0x12345678, bytes(&mut insts)).unwrap();
+        assert_eq!(insts, [0x12345097, 0x678080e7]);
+        /* lower 12bit is sign-extended, upper 20bit is rounded up */
+        let mut insts = [0x00000097u32, 0x000080e7];
+        patch_pcrel(RelaType::CALL_PLT, 0x800, bytes(&mut insts)).unwrap();
+        assert_eq!(insts, [0x00001097, 0x800080e7]);
+    }
+
+    #[test]
+    fn absolute_hi20_lo12_encoding() {
+        /* lui a0, 0; addi a0, a0, 0; sw a0, 0(a0) */
+        let mut insts = [0x00000537u32, 0x00050513, 0x00a52023];
+        let place = bytes(&mut insts);
+        patch(RelaType::HI20, 0x12345fff, &mut place[0..4]).unwrap();
+        patch(RelaType::LO12_I, 0x12345fff, &mut place[4..8]).unwrap();
+        patch(RelaType::LO12_S, 0x12345fff, &mut place[8..12]).unwrap();
+        assert_eq!(insts, [0x12346537, 0xfff50513, 0xfea52fa3]);
+    }
+
+    #[test]
+    fn pcrel_lo12_takes_value_of_its_hi20() {
+        /* auipc a0, 0; addi a0, a0, 0; sw a0, 0(a0) */
+        let mut insts = [0x00000517u32, 0x00050513, 0x00a52023];
+        let place = bytes(&mut insts);
+        let label = place.as_ptr() as usize;
+        let hipcrel = 0x12345fff;
+        patch_pcrel(RelaType::PCREL_HI20, hipcrel, &mut place[0..4]).unwrap();
+        let hi20_values = BTreeMap::from([(label, hipcrel as usize)]);
+        /* symbol of PCREL_LO12 is the label of auipc, not the target */
+        unsafe {
+            ELFFile::patch_value(
+                RelaType::PCREL_LO12_I,
+                label,
+                &mut place[4..8],
+                &hi20_values,
+            )
+            .unwrap();
+            ELFFile::patch_value(
+                RelaType::PCREL_LO12_S,
+                label,
+                &mut place[8..12],
+                &hi20_values,
+            )
+            .unwrap();
+        }
+        assert_eq!(insts, [0x12346517, 0xfff50513, 0xfea52fa3]);
+    }
+
+    #[test]
+    fn pcrel_lo12_without_hi20() {
+        let mut insts = [0x00050513u32];
+        let place = bytes(&mut insts);
+        let label = place.as_ptr() as usize - 4;
+        assert!(matches!(
+            patch(RelaType::PCREL_LO12_I, label, place),
+            Err(RelocateError::PcrelHi20NotFound(v)) if v == label
+        ));
+    }
+
+    #[test]
+    fn range_of_pcrel_fields() {
+        let fits = |rtype, pcrel, size| {
+            let mut insts = [0u32];
+            let ok = patch_pcrel(rtype, pcrel, &mut bytes(&mut insts)[..size]).is_ok();
+            /* nothing is written on overflow */
+            assert!(ok || insts[0] == 0);
+            ok
+        };
+        use RelaType::*;
+        [
+            (BRANCH, 4, 4094, 4096, -4096, -4098),
+            (JAL, 4, 0xffffe, 0x100000, -0x100000, -0x100002),
+            (RVC_BRANCH, 2, 254, 256, -256, -258),
+            (RVC_JUMP, 2, 2046, 2048, -2048, -2050),
+        ]
+        .iter()
+        .for_each(|&(rtype, size, max, over, min, under)| {
+            assert!(fits(rtype, max, size), "{:?}", rtype);
+            assert!(!fits(rtype, over, size), "{:?}", rtype);
+            assert!(fits(rtype, min, size), "{:?}", rtype);
+            assert!(!fits(rtype, under, size), "{:?}", rtype);
+            /* targets are 2 byte aligned */
+            assert!(!fits(rtype, 3, size), "{:?}", rtype);
+        });
+    }
+
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn range_of_hi20_pairs() {
+        let mut insts = [0x00000097u32, 0x000080e7];
+        assert!(patch_pcrel(RelaType::CALL, 0x7ffff7ff, bytes(&mut insts)).is_ok());
+        let mut insts = [0x00000097u32, 0x000080e7];
+        assert!(matches!(
+            patch_pcrel(RelaType::CALL, 0x7ffff800, bytes(&mut insts)),
+            Err(RelocateError::Overflow(RelaType::CALL, _))
+        ));
+        assert_eq!(insts, [0x00000097, 0x000080e7]);
+        assert!(patch(
+            RelaType::HI20,
+            -0x80000800isize as usize,
+            bytes(&mut [0u32])
+        )
+        .is_ok());
+        assert!(patch(RelaType::HI20, 0x80000000, bytes(&mut [0u32])).is_err());
+    }
+
+    #[test]
+    fn rvc_lui_needs_nonzero_hi20() {
+        /* c.lui a0, 1 */
+        let mut insts = [0x00006505u32];
+        let place = &mut bytes(&mut insts)[..2];
+        assert!(matches!(
+            patch(RelaType::RVC_LUI, 0x7ff, place),
+            Err(RelocateError::Overflow(RelaType::RVC_LUI, _))
+        ));
+        assert!(patch(RelaType::RVC_LUI, 0x20000, place).is_err());
+        patch(RelaType::RVC_LUI, 0x1f000, place).unwrap();
+        assert_eq!(insts[0], 0x0000657d);
+    }
+
+    #[test]
+    fn set_uleb128_keeps_length() {
+        let mut place = [0x80u8, 0x80, 0x00, 0xff];
+        patch(RelaType::SET_ULEB128, 5, &mut place).unwrap();
+        assert_eq!(place, [0x85, 0x80, 0x00, 0xff]);
+        /* 200 needs 2 bytes */
+        let mut place = [0x00u8, 0xff];
+        assert!(matches!(
+            patch(RelaType::SET_ULEB128, 200, &mut place),
+            Err(RelocateError::Overflow(RelaType::SET_ULEB128, 200))
+        ));
+        assert_eq!(place, [0x00, 0xff]);
+        let mut place = [0x80u8, 0x80];
+        assert!(matches!(
+            patch(RelaType::SET_ULEB128, 5, &mut place),
+            Err(RelocateError::Malformed(RelaType::SET_ULEB128, _))
+        ));
+    }
+
+    #[test]
+    fn align_needs_aligned_padding_end() {
+        /* 2 bytes of padding for a 4 byte boundary */
+        let mut insts = [0u32; 2];
+        let place = bytes(&mut insts);
+        assert!(patch(RelaType::ALIGN, 2, &mut place[2..]).is_ok());
+        assert!(matches!(
+            patch(RelaType::ALIGN, 2, &mut place[0..]),
+            Err(RelocateError::Unsupported(RelaType::ALIGN))
+        ));
+        assert!(matches!(
+            patch(RelaType::ALIGN, usize::MAX - 1, &mut place[0..]),
+            Err(RelocateError::Overflow(RelaType::ALIGN, _))
+        ));
+    }
+
+    #[test]
+    fn tprel_is_unsupported() {
+        let mut insts = [0x00000537u32];
+        [
+            RelaType::TPREL_HI20,
+            RelaType::TPREL_LO12_I,
+            RelaType::TPREL_ADD,
+        ]
+        .iter()
+        .for_each(|&rtype| {
+            assert!(matches!(
+                patch(rtype, 0, bytes(&mut insts)),
+                Err(RelocateError::Unsupported(_))
+            ))
+        });
+        assert_eq!(insts[0], 0x00000537);
+    }
+}
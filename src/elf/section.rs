@@ -5,6 +5,7 @@ pub use rela::RelaType;
 pub use symbol::{STBind, STType, STVis};
 
 #[repr(C)]
+#[derive(Clone)]
 pub struct Rela {
     pub r_offset: usize,
     pub r_info: usize,
@@ -13,6 +14,7 @@ pub struct Rela {
 
 #[cfg(target_pointer_width = "32")]
 #[repr(C)]
+#[derive(Clone)]
 pub struct Symbol {
     pub st_name: u32,
     pub st_value: usize,
@@ -24,6 +26,7 @@ pub struct Symbol {
 
 #[cfg(target_pointer_width = "64")]
 #[repr(C)]
+#[derive(Clone)]
 pub struct Symbol {
     pub st_name: u32,
     pub st_info: u8,
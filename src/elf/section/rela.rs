@@ -24,8 +24,25 @@ impl Rela {
         }
     }
 
-    pub const fn rela_type(&self) -> RelaType {
-        unsafe { core::mem::transmute(self._rela_type()) }
+    pub fn rela_type(&self) -> RelaType {
+        let raw = self._rela_type() as u32;
+        RelaType::try_from(raw).unwrap_or_else(RelaType::Unknown)
+    }
+
+    /* bytes touched at `r_offset` while relocating */
+    pub fn patch_size(&self) -> usize {
+        use RelaType::*;
+        match self.rela_type() {
+            RISCV_64 | ADD64 | SUB64 => 8,
+            /* auipc + jalr */
+            CALL | CALL_PLT => 8,
+            /* ULEB128 is at least one byte, the rest is checked while relocating */
+            ADD8 | SUB8 | SUB6 | SET6 | SET8 | SET_ULEB128 | SUB_ULEB128 => 1,
+            ADD16 | SUB16 | SET16 | RVC_BRANCH | RVC_JUMP | RVC_LUI => 2,
+            NONE | RELAX | ALIGN | TPREL_ADD | GNU_VTINHERIT | GNU_VTENTRY => 0,
+            Unknown(_) => 0,
+            _ => 4,
+        }
     }
 
     const fn _rela_type(&self) -> usize {
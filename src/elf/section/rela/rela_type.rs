@@ -1,61 +1,134 @@
 #[allow(non_camel_case_types)]
-#[repr(usize)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RelaType {
     /* Relocation types used by the dynamic linker */
-    // NONE = 0,
-    RISCV_32 = 1,
-    RISCV_64 = 2,
-    // RELATIVE = 3,
-    // COPY = 4,
-    // JUMP_SLOT = 5,
-    // TLS_DTPMOD32 = 6,
-    // TLS_DTPMOD64 = 7,
-    // TLS_DTPREL32 = 8,
-    // TLS_DTPREL64 = 9,
-    // TLS_TPREL32 = 10,
-    // TLS_TPREL64 = 11,
+    NONE,
+    RISCV_32,
+    RISCV_64,
+    RELATIVE,
+    COPY,
+    JUMP_SLOT,
+    TLS_DTPMOD32,
+    TLS_DTPMOD64,
+    TLS_DTPREL32,
+    TLS_DTPREL64,
+    TLS_TPREL32,
+    TLS_TPREL64,
     /* Relocation types not used by the dynamic linker */
-    BRANCH = 16,
-    // JAL = 17,
-    CALL = 18,
-    CALL_PLT = 19,
-    // GOT_HI20 = 20,
-    // TLS_GOT_HI20 = 21,
-    // TLS_GD_HI20 = 22,
-    PCREL_HI20 = 23,
-    PCREL_LO12_I = 24,
-    PCREL_LO12_S = 25,
-    HI20 = 26,
-    LO12_I = 27,
-    LO12_S = 28,
-    // TPREL_HI20 = 29,
-    // TPREL_LO12_I = 30,
-    // TPREL_LO12_S = 31,
-    // TPREL_ADD = 32,
-    // ADD8 = 33,
-    // ADD16 = 34,
-    // ADD32 = 35,
-    // ADD64 = 36,
-    // SUB8 = 37,
-    // SUB16 = 38,
-    // SUB32 = 39,
-    // SUB64 = 40,
-    // GNU_VTINHERIT = 41,
-    // GNU_VTENTRY = 42,
-    // ALIGN = 43,
-    RVC_BRANCH = 44,
-    RVC_JUMP = 45,
-    // RVC_LUI = 46,
-    // GPREL_I = 47,
-    // GPREL_S = 48,
-    // TPREL_I = 49,
-    // TPREL_S = 50,
-    RELAX = 51,
-    // SUB6 = 52,
-    // SET6 = 53,
-    // SET8 = 54,
-    // SET16 = 55,
-    // SET32 = 56,
-    // RV32_PCREL = 57,
+    BRANCH,
+    JAL,
+    CALL,
+    CALL_PLT,
+    GOT_HI20,
+    TLS_GOT_HI20,
+    TLS_GD_HI20,
+    PCREL_HI20,
+    PCREL_LO12_I,
+    PCREL_LO12_S,
+    HI20,
+    LO12_I,
+    LO12_S,
+    TPREL_HI20,
+    TPREL_LO12_I,
+    TPREL_LO12_S,
+    TPREL_ADD,
+    ADD8,
+    ADD16,
+    ADD32,
+    ADD64,
+    SUB8,
+    SUB16,
+    SUB32,
+    SUB64,
+    GNU_VTINHERIT,
+    GNU_VTENTRY,
+    ALIGN,
+    RVC_BRANCH,
+    RVC_JUMP,
+    RVC_LUI,
+    GPREL_I,
+    GPREL_S,
+    TPREL_I,
+    TPREL_S,
+    RELAX,
+    SUB6,
+    SET6,
+    SET8,
+    SET16,
+    SET32,
+    RISCV_32_PCREL,
+    IRELATIVE,
+    PLT32,
+    SET_ULEB128,
+    SUB_ULEB128,
+    Unknown(u32),
+}
+
+impl TryFrom<u32> for RelaType {
+    type Error = u32;
+
+    fn try_from(v: u32) -> Result<Self, Self::Error> {
+        use RelaType::*;
+        match v {
+            0 => Ok(NONE),
+            1 => Ok(RISCV_32),
+            2 => Ok(RISCV_64),
+            3 => Ok(RELATIVE),
+            4 => Ok(COPY),
+            5 => Ok(JUMP_SLOT),
+            6 => Ok(TLS_DTPMOD32),
+            7 => Ok(TLS_DTPMOD64),
+            8 => Ok(TLS_DTPREL32),
+            9 => Ok(TLS_DTPREL64),
+            10 => Ok(TLS_TPREL32),
+            11 => Ok(TLS_TPREL64),
+            16 => Ok(BRANCH),
+            17 => Ok(JAL),
+            18 => Ok(CALL),
+            19 => Ok(CALL_PLT),
+            20 => Ok(GOT_HI20),
+            21 => Ok(TLS_GOT_HI20),
+            22 => Ok(TLS_GD_HI20),
+            23 => Ok(PCREL_HI20),
+            24 => Ok(PCREL_LO12_I),
+            25 => Ok(PCREL_LO12_S),
+            26 => Ok(HI20),
+            27 => Ok(LO12_I),
+            28 => Ok(LO12_S),
+            29 => Ok(TPREL_HI20),
+            30 => Ok(TPREL_LO12_I),
+            31 => Ok(TPREL_LO12_S),
+            32 => Ok(TPREL_ADD),
+            33 => Ok(ADD8),
+            34 => Ok(ADD16),
+            35 => Ok(ADD32),
+            36 => Ok(ADD64),
+            37 => Ok(SUB8),
+            38 => Ok(SUB16),
+            39 => Ok(SUB32),
+            40 => Ok(SUB64),
+            41 => Ok(GNU_VTINHERIT),
+            42 => Ok(GNU_VTENTRY),
+            43 => Ok(ALIGN),
+            44 => Ok(RVC_BRANCH),
+            45 => Ok(RVC_JUMP),
+            46 => Ok(RVC_LUI),
+            47 => Ok(GPREL_I),
+            48 => Ok(GPREL_S),
+            49 => Ok(TPREL_I),
+            50 => Ok(TPREL_S),
+            51 => Ok(RELAX),
+            52 => Ok(SUB6),
+            53 => Ok(SET6),
+            54 => Ok(SET8),
+            55 => Ok(SET16),
+            56 => Ok(SET32),
+            57 => Ok(RISCV_32_PCREL),
+            58 => Ok(IRELATIVE),
+            59 => Ok(PLT32),
+            60 => Ok(SET_ULEB128),
+            61 => Ok(SUB_ULEB128),
+            _ => Err(v),
+        }
+    }
 }
@@ -15,10 +15,6 @@ impl Symbol {
         self.st_value
     }
 
-    pub fn symbol_value_set(&mut self, v: usize) {
-        self.st_value = v;
-    }
-
     pub const fn symbol_size(&self) -> usize {
         self.st_size
     }
@@ -31,16 +27,22 @@ impl Symbol {
         self.st_shndx as usize
     }
 
-    pub const fn symbol_type(&self) -> STType {
-        unsafe { core::mem::transmute(self.st_info & 0xf) }
+    pub fn symbol_type(&self) -> STType {
+        STType::try_from(self.st_info & 0xf).unwrap_or_else(STType::Unknown)
+    }
+
+    pub fn symbol_bind(&self) -> STBind {
+        STBind::try_from(self.st_info >> 4).unwrap_or_else(STBind::Unknown)
     }
 
-    pub const fn symbol_bind(&self) -> STBind {
-        unsafe { core::mem::transmute(self.st_info >> 4) }
+    pub fn symbol_visibility(&self) -> STVis {
+        STVis::try_from(self.st_other & 0x3).unwrap_or_else(STVis::Unknown)
     }
 
-    pub const fn symbol_visibility(&self) -> STVis {
-        unsafe { core::mem::transmute(self.st_other & 0x3) }
+    /* visible to other modules, LOCAL, FILE and hidden symbols stay private */
+    pub fn is_exported(&self) -> bool {
+        matches!(self.symbol_bind(), STBind::GLOBAL | STBind::WEAK)
+            && matches!(self.symbol_visibility(), STVis::DEFAULT | STVis::PROTECTED)
     }
 }
 
@@ -1,11 +1,29 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum STBind {
-    LOCAL = 0,
-    GLOBAL = 1,
-    WEAK = 2,
-    LOOS = 10,
-    HIOS = 12,
-    LOPROC = 13,
-    HIPROC = 15,
+    LOCAL,
+    GLOBAL,
+    WEAK,
+    LOOS,
+    HIOS,
+    LOPROC,
+    HIPROC,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for STBind {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use STBind::*;
+        match v {
+            0 => Ok(LOCAL),
+            1 => Ok(GLOBAL),
+            2 => Ok(WEAK),
+            10 => Ok(LOOS),
+            12 => Ok(HIOS),
+            13 => Ok(LOPROC),
+            15 => Ok(HIPROC),
+            _ => Err(v),
+        }
+    }
 }
@@ -1,15 +1,37 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum STType {
-    NOTYPE = 0,
-    OBJECT = 1,
-    FUNC = 2,
-    SECTION = 3,
-    FILE = 4,
-    COMMON = 5,
-    TLS = 6,
-    LOOS = 10,
-    HIOS = 12,
-    LOPROC = 13,
-    HIPROC = 15,
+    NOTYPE,
+    OBJECT,
+    FUNC,
+    SECTION,
+    FILE,
+    COMMON,
+    TLS,
+    LOOS,
+    HIOS,
+    LOPROC,
+    HIPROC,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for STType {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use STType::*;
+        match v {
+            0 => Ok(NOTYPE),
+            1 => Ok(OBJECT),
+            2 => Ok(FUNC),
+            3 => Ok(SECTION),
+            4 => Ok(FILE),
+            5 => Ok(COMMON),
+            6 => Ok(TLS),
+            10 => Ok(LOOS),
+            12 => Ok(HIOS),
+            13 => Ok(LOPROC),
+            15 => Ok(HIPROC),
+            _ => Err(v),
+        }
+    }
 }
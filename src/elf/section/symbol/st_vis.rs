@@ -1,8 +1,23 @@
-#[repr(u8)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum STVis {
-    DEFAULT = 0,
-    INTERNAL = 1,
-    HIDDEN = 2,
-    PROTECTED = 3,
+    DEFAULT,
+    INTERNAL,
+    HIDDEN,
+    PROTECTED,
+    Unknown(u8),
+}
+
+impl TryFrom<u8> for STVis {
+    type Error = u8;
+
+    fn try_from(v: u8) -> Result<Self, Self::Error> {
+        use STVis::*;
+        match v {
+            0 => Ok(DEFAULT),
+            1 => Ok(INTERNAL),
+            2 => Ok(HIDDEN),
+            3 => Ok(PROTECTED),
+            _ => Err(v),
+        }
+    }
 }
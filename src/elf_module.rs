@@ -1,82 +1,391 @@
 use alloc::alloc::{alloc_zeroed, dealloc, Layout};
 use alloc::boxed::Box;
-use alloc::collections::BTreeMap;
 use alloc::collections::LinkedList;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::ffi::CString;
 use alloc::rc;
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
+use alloc::vec;
 use alloc::vec::Vec;
 use core::cell::RefCell;
-use core::intrinsics;
+use core::fmt::{self, Debug};
+use core::mem;
 use core::ptr;
 use core::slice;
 
-use crate::elf::headers::{SHFlags, SHType};
-use crate::elf::section::{Rela, STBind, Symbol};
-use crate::elf::ELFFile;
+use crate::elf::headers::{SHFlags, SHType, SHeader};
+use crate::elf::section::{Rela, RelaType, STBind, STType, Symbol};
+use crate::elf::{ELFFile, ELFFileError, RelocateError};
+use crate::export;
+
+mod placement;
+
+pub use placement::{Placement, PlacementRule, RegionAlloc, RegionAllocator, SectionPlacement};
 
 #[derive(Debug)]
 pub struct ElfModuleRoot {
     pub modules: LinkedList<rc::Rc<RefCell<ElfModule>>>,
+    /* registered by host at runtime, see also `export_symbol!` */
+    pub host_symbols: BTreeMap<String, *const u8>,
+    /* exported symbols of all modules in loading order, maintained on load and unload */
+    pub symbol_index: BTreeMap<String, Vec<(rc::Weak<RefCell<ElfModule>>, SymbolDef)>>,
+    /* asked last for undefined symbols */
+    pub resolver: Option<Box<dyn SymbolResolver>>,
+    /* load and relocate .debug_* sections too, for debugging on target */
+    pub load_debug: bool,
+    /* which region each section is allocated in */
+    pub placement: Placement,
+    /* applied to modules after relocation, reverted at unload */
+    pub protector: Option<Box<dyn MemoryProtector>>,
+    /* fence.i of the loading hart if not set */
+    pub cache_sync: Option<Box<dyn CacheSync>>,
+}
+
+/* supplies host symbols on demand, e.g. from a syscall table or generated stubs */
+pub trait SymbolResolver {
+    /* asked for each symbol not found in host symbols or loaded modules */
+    fn resolve(&self, name: &str) -> Option<*const u8>;
+    /* all symbols still missing, the load fails after it */
+    fn missing(&self, _names: &[&str]) {}
+}
+
+impl Debug for dyn SymbolResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SymbolResolver")
+    }
+}
+
+/* permissions passed to MemoryProtector, same values as mprotect */
+pub const PROT_READ: u32 = 0x1;
+pub const PROT_WRITE: u32 = 0x2;
+pub const PROT_EXEC: u32 = 0x4;
+
+/* keeps loaded memory W^X, e.g. by PMP, an MPU or mprotect */
+pub trait MemoryProtector {
+    /* set `prot` of PROT_* to `size` bytes at `ptr`, false if it can't be applied */
+    fn protect(&self, ptr: *mut u8, size: usize, prot: u32) -> bool;
+}
+
+impl Debug for dyn MemoryProtector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MemoryProtector")
+    }
+}
+
+/* makes written instructions visible to instruction fetch, e.g. for an external cache */
+pub trait CacheSync {
+    fn sync(&self, ptr: *const u8, size: usize);
+}
+
+impl Debug for dyn CacheSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CacheSync")
+    }
+}
+
+/* only the loading hart is synchronized, other harts need their own fence.i */
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+fn fence_i() {
+    /* fence.i encoded, Zifencei isn't implied by every target */
+    unsafe { core::arch::asm!(".word 0x0000100f") }
+}
+
+#[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
+fn fence_i() {}
+
+/* what is kept of a defined symbol */
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolDef {
+    pub addr: *const u8,
+    pub size: usize,
+    pub st_info: u8,
+}
+
+impl SymbolDef {
+    fn new(s: &Symbol, value: usize) -> Self {
+        SymbolDef {
+            addr: value as *const u8,
+            size: s.st_size,
+            st_info: s.st_info,
+        }
+    }
+
+    pub fn symbol_type(&self) -> STType {
+        STType::try_from(self.st_info & 0xf).unwrap_or_else(STType::Unknown)
+    }
+
+    pub fn symbol_bind(&self) -> STBind {
+        STBind::try_from(self.st_info >> 4).unwrap_or_else(STBind::Unknown)
+    }
+
+    fn info(&self, module: *const ElfModule) -> SymbolInfo {
+        SymbolInfo {
+            module,
+            addr: self.addr,
+            size: self.size,
+            st_type: self.st_info & 0xf,
+            st_bind: self.st_info >> 4,
+        }
+    }
+}
+
+/* keep in sync with `elf_sym_info_t` in include/elfloader_fcn.h,
+ * `st_type` and `st_bind` are raw STT_* and STB_* */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SymbolInfo {
+    pub module: *const ElfModule,
+    pub addr: *const u8,
+    pub size: usize,
+    pub st_type: u8,
+    pub st_bind: u8,
+}
+
+impl SymbolInfo {
+    pub fn symbol_type(&self) -> STType {
+        STType::try_from(self.st_type).unwrap_or_else(STType::Unknown)
+    }
+
+    pub fn symbol_bind(&self) -> STBind {
+        STBind::try_from(self.st_bind).unwrap_or_else(STBind::Unknown)
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    Parse(ELFFileError),
+    UndefinedSymbol(String),
+    SymbolConflict(String),
+    CommonSymbol(String),
+    LayoutInvalid(usize, usize),
+    OutOfMemory(Layout),
+    Relocation(RelocateError),
+    /* relocation type, section, offset in section and symbol */
+    RelocationOverflow(RelaType, String, usize, String),
+    /* value returned by module_init */
+    InitFailed(i32),
+    /* region which MemoryProtector refused */
+    ProtectFailed(usize),
+}
+
+/* entry and exit of each module, they are never exported */
+const MODULE_ENTRIES: [&str; 2] = ["module_init", "module_exit"];
+
+/* keep in sync with `elf_addr_info_t` in include/elfloader_fcn.h,
+ * `symbol` is NUL-terminated and null if no symbol is before the address */
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct AddrInfo {
+    pub module: *const ElfModule,
+    pub region: usize,
+    pub symbol: *const u8,
+    pub symbol_addr: *const u8,
+    pub offset: usize,
+}
+
+/* keep in sync with `elf_error_t` in include/elfloader_fcn.h */
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum LoadErrorCode {
+    OK = 0,
+    PARSE = 1,
+    UNDEFINED_SYMBOL = 2,
+    SYMBOL_CONFLICT = 3,
+    UNSUPPORTED_RELOCATION = 4,
+    RELOCATION = 5,
+    NO_MEMORY = 6,
+    COMMON_SYMBOL = 7,
+    INIT_FAILED = 8,
+    PROTECT_FAILED = 9,
+}
+
+impl LoadErrorCode {
+    /* C may pass any int, never transmute it */
+    pub const fn from_raw(raw: i32) -> Option<Self> {
+        use LoadErrorCode::*;
+        match raw {
+            0 => Some(OK),
+            1 => Some(PARSE),
+            2 => Some(UNDEFINED_SYMBOL),
+            3 => Some(SYMBOL_CONFLICT),
+            4 => Some(UNSUPPORTED_RELOCATION),
+            5 => Some(RELOCATION),
+            6 => Some(NO_MEMORY),
+            7 => Some(COMMON_SYMBOL),
+            8 => Some(INIT_FAILED),
+            9 => Some(PROTECT_FAILED),
+            _ => None,
+        }
+    }
+
+    pub const fn message(&self) -> &'static str {
+        use LoadErrorCode::*;
+        match self {
+            OK => "success\0",
+            PARSE => "elf file is corrupt or not supported\0",
+            UNDEFINED_SYMBOL => "undefined symbol can't be resolved\0",
+            SYMBOL_CONFLICT => "global symbol has conflict\0",
+            UNSUPPORTED_RELOCATION => "relocation type is not supported\0",
+            RELOCATION => "relocation can't be applied\0",
+            NO_MEMORY => "out of memory\0",
+            COMMON_SYMBOL => "common symbol is larger than the definition to merge with\0",
+            INIT_FAILED => "module_init returned non-zero\0",
+            PROTECT_FAILED => "memory protection can't be applied\0",
+        }
+    }
+}
+
+impl LoadError {
+    pub const fn code(&self) -> LoadErrorCode {
+        match self {
+            LoadError::Parse(ELFFileError::OutOfMemory(_)) => LoadErrorCode::NO_MEMORY,
+            /* sizes and alignments come from the object */
+            LoadError::Parse(_) | LoadError::LayoutInvalid(..) => LoadErrorCode::PARSE,
+            LoadError::UndefinedSymbol(_) => LoadErrorCode::UNDEFINED_SYMBOL,
+            LoadError::SymbolConflict(_) => LoadErrorCode::SYMBOL_CONFLICT,
+            LoadError::CommonSymbol(_) => LoadErrorCode::COMMON_SYMBOL,
+            LoadError::OutOfMemory(_) => LoadErrorCode::NO_MEMORY,
+            LoadError::Relocation(RelocateError::Unsupported(_)) => {
+                LoadErrorCode::UNSUPPORTED_RELOCATION
+            }
+            LoadError::Relocation(_) | LoadError::RelocationOverflow(..) => {
+                LoadErrorCode::RELOCATION
+            }
+            LoadError::InitFailed(_) => LoadErrorCode::INIT_FAILED,
+            LoadError::ProtectFailed(_) => LoadErrorCode::PROTECT_FAILED,
+        }
+    }
+}
+
+impl From<ELFFileError> for LoadError {
+    fn from(err: ELFFileError) -> Self {
+        LoadError::Parse(err)
+    }
+}
+
+impl From<RelocateError> for LoadError {
+    fn from(err: RelocateError) -> Self {
+        LoadError::Relocation(err)
+    }
 }
 
 impl ElfModuleRoot {
-    pub fn load_elf_file(&mut self, elf_file: &ELFFile) -> Option<*const ElfModule> {
-        let und_sym_names = elf_file.get_undefined_symbol_names();
+    /* nothing in root is touched unless the whole module is loaded */
+    pub fn load_elf_file(&mut self, elf_file: &ELFFile) -> Result<*const ElfModule, LoadError> {
         /* try find undefined global symbols */
-        let und_syms = und_sym_names
-            .iter()
-            .map(|&name| {
-                let sym_and_weak = self
-                    .find_symbol_and_weak(name)
-                    .unwrap_or((ptr::null(), rc::Weak::default()));
-                (name, sym_and_weak)
-            })
+        let und_syms = elf_file
+            .get_undefined_symbols()
+            .into_iter()
+            .map(|(name, s)| (name, s, self.find_symbol_and_weak(name)))
             .collect::<Vec<_>>();
 
-        /* return None while can't find undefined */
-        if und_syms.iter().any(|us| us.1 .0.is_null()) {
-            println!("{:?}", und_syms);
+        /* fail while can't find undefined, but weak one is left to be 0 */
+        let missing = und_syms
+            .iter()
+            .filter(|(_, s, found)| found.is_none() && !matches!(s.symbol_bind(), STBind::WEAK))
+            .map(|&(name, _, _)| name)
+            .collect::<Vec<_>>();
+        if let Some(name) = missing.first() {
+            if let Some(resolver) = self.resolver.as_ref() {
+                resolver.missing(&missing);
+            }
+            println!("{:?}", missing);
             println!("[failed]undefined symbol can't be resolved");
-            return None;
+            return Err(LoadError::UndefinedSymbol(name.to_string()));
         }
+        let mut und_syms = und_syms
+            .into_iter()
+            .filter_map(|(name, _, found)| Some((name, found?)))
+            .collect::<Vec<_>>();
 
-        if elf_file
-            .get_all_symbol_names()
-            .iter()
-            .any(|name| self.find_symbol(name).is_some())
+        /* COMMON is merged with an existing definition as a linker does, or allocated here */
+        let mut commons = Vec::new();
+        for (name, s) in elf_file.get_common_symbols() {
+            match self.find_symbol_and_weak(name) {
+                Some(_)
+                    if self
+                        .find_symbol_info(name)
+                        .map_or(false, |i| i.size < s.st_size) =>
+                {
+                    println!("[failed]common symbol {} can't be merged", name);
+                    return Err(LoadError::CommonSymbol(name.to_string()));
+                }
+                Some(found) => und_syms.push((name, found)),
+                None => commons.push((name, s)),
+            }
+        }
+
+        /* weak definition never conflicts, and can be overridden by a strong one */
+        if let Some((name, _)) = elf_file
+            .get_defined_symbols()
+            .into_iter()
+            .find(|&(name, s)| {
+                s.is_exported()
+                    && s.st_shndx != 0xfff2
+                    && !MODULE_ENTRIES.contains(&name)
+                    && !matches!(s.symbol_bind(), STBind::WEAK)
+                    && self
+                        .find_module_symbol(name, true)
+                        .map(|(symaddr, _)| symaddr)
+                        .or_else(|| self.find_host_symbol(name))
+                        .is_some()
+            })
         {
             println!("[failed]global symbol has conflict");
-            return None;
+            return Err(LoadError::SymbolConflict(name.to_string()));
         }
 
+        /* module is dropped with its memory on any failure */
         let em = ElfModule::new()
-            /* allocate memory for text and data */
-            .alloc_memory_with(&elf_file)
+            /* allocate memory of each region and place sections in */
+            .alloc_memory_with(&elf_file, &commons, &self.placement)?
             /* fill undefined global symbols */
             .fill_undefined_symbols(und_syms)
             /* load section data into memory */
-            .load_into_memory(&elf_file)
+            .load_into_memory(&elf_file)?
+            /* load debug sections into their own buffer */
+            .load_debug_with(&elf_file, self.load_debug)?
             /* update symbol value */
-            .update_symbol_value_with(&elf_file)
+            .update_symbol_value_with(&elf_file)?
             /* relocate text and data */
-            .relocate_symbols_with(&elf_file);
-
-        self.modules.push_back(rc::Rc::new(RefCell::new(em)));
-        self.modules.back().and_then(|rcem| {
-            rcem.borrow()
-                .dependencies
-                .iter()
-                .map(|pm| {
-                    Some(pm.upgrade().and_then(|spm| {
-                        Some((*spm).borrow_mut().dependents.push(rc::Rc::clone(&rcem)))
-                    }))
-                })
-                .count();
+            .relocate_symbols_with(&elf_file)?
+            /* find constructors and destructors */
+            .collect_init_fini_with(&elf_file)
+            /* drop stale instructions of reused memory */
+            .sync_icache_with(self.cache_sync.as_deref())
+            /* make text and rodata read-only */
+            .protect_with(self.protector.as_deref())?;
 
-            rcem.borrow().print_text_and_data();
+        let rcem = rc::Rc::new(RefCell::new(em));
+        self.modules.push_back(rc::Rc::clone(&rcem));
+        self.index_symbols(&rcem);
+        rcem.borrow()
+            .dependencies
+            .iter()
+            .map(|pm| {
+                Some(pm.upgrade().and_then(|spm| {
+                    Some((*spm).borrow_mut().dependents.push(rc::Rc::clone(&rcem)))
+                }))
+            })
+            .count();
 
-            Some(rcem.as_ptr() as *const ElfModule)
-        })
+        rcem.borrow().print_regions();
+        /* copied out, constructors may call back into the loader */
+        let init_funcs = rcem.borrow().init_funcs.clone();
+        unsafe { ElfModule::call_funcs(&init_funcs) };
+        /* module_init decides whether the module stays, module_exit isn't called if not */
+        let init_func = rcem.borrow().init_func;
+        let ret = init_func.map_or(0, |f| unsafe {
+            mem::transmute::<usize, extern "C" fn() -> i32>(f)()
+        });
+        if ret != 0 {
+            println!("[failed]module_init returned {}", ret);
+            self.unload_elf_module(rcem.as_ptr());
+            return Err(LoadError::InitFailed(ret));
+        }
+        rcem.borrow_mut().initialized = true;
+        Ok(rcem.as_ptr() as *const ElfModule)
     }
 
     pub fn unload_elf_module(&mut self, elf_module: *const ElfModule) {
@@ -103,24 +412,138 @@ impl ElfModuleRoot {
                     idx
                 })
             })
-            .and_then(|idx| Some(self.modules.remove(idx)));
+            .map(|idx| self.modules.remove(idx))
+            .map(|em| {
+                /* module_exit and destructors run while the module is still resolvable */
+                let exit_func = em.borrow().initialized.then(|| em.borrow().exit_func);
+                if let Some(f) = exit_func.flatten() {
+                    unsafe { mem::transmute::<usize, extern "C" fn()>(f)() };
+                }
+                let fini_funcs = em.borrow().fini_funcs.clone();
+                unsafe { ElfModule::call_funcs(&fini_funcs) };
+                self.unindex_symbols(&em);
+                /* writable again before the memory goes back to its region */
+                if let Some(protector) = self.protector.as_deref() {
+                    em.borrow_mut().unprotect_with(protector)
+                }
+                /* no stale instructions are left for whoever gets the memory next */
+                em.borrow().sync_icache(self.cache_sync.as_deref())
+            });
+    }
+
+    /* the module and nearest symbol which `addr` lies in */
+    pub fn addr_info(&self, addr: *const u8) -> Option<AddrInfo> {
+        self.modules.iter().find_map(|m| m.borrow().addr_info(addr))
+    }
+
+    fn index_symbols(&mut self, rcem: &rc::Rc<RefCell<ElfModule>>) {
+        rcem.borrow().symbol_info.iter().for_each(|(&name, &def)| {
+            self.symbol_index
+                .entry(name.to_string())
+                .or_default()
+                .push((rc::Rc::downgrade(rcem), def))
+        });
     }
 
+    fn unindex_symbols(&mut self, rcem: &rc::Rc<RefCell<ElfModule>>) {
+        let pm = rc::Rc::downgrade(rcem);
+        rcem.borrow().symbol_info.keys().for_each(|&name| {
+            if let Some(defs) = self.symbol_index.get_mut(name) {
+                defs.retain(|(m, _)| !m.ptr_eq(&pm));
+                if defs.is_empty() {
+                    self.symbol_index.remove(name);
+                }
+            }
+        });
+    }
+
+    /* strong definitions take precedence over weak ones */
     pub fn find_symbol(&self, name: &str) -> Option<*const u8> {
-        self.modules
+        self.find_module_symbol(name, true)
+            .or_else(|| self.find_module_symbol(name, false))
+            .map(|(symaddr, _)| symaddr)
+    }
+
+    pub fn find_symbol_info(&self, name: &str) -> Option<SymbolInfo> {
+        self.find_indexed(name, true)
+            .or_else(|| self.find_indexed(name, false))
+            .map(|(m, def)| def.info(m.upgrade().map_or(ptr::null(), |m| m.as_ptr())))
+    }
+
+    fn find_module_symbol(
+        &self,
+        name: &str,
+        strong: bool,
+    ) -> Option<(*const u8, rc::Weak<RefCell<ElfModule>>)> {
+        self.find_indexed(name, strong)
+            .map(|(m, def)| (def.addr, m.clone()))
+    }
+
+    fn find_indexed(
+        &self,
+        name: &str,
+        strong: bool,
+    ) -> Option<&(rc::Weak<RefCell<ElfModule>>, SymbolDef)> {
+        self.symbol_index
+            .get(name)?
             .iter()
-            .find_map(|m| m.borrow().find_symbol(name))
+            .find(|(_, def)| matches!(def.symbol_bind(), STBind::WEAK) != strong)
     }
 
+    pub fn find_host_symbol(&self, name: &str) -> Option<*const u8> {
+        self.host_symbols
+            .get(name)
+            .copied()
+            .or_else(|| export::find_export(name))
+    }
+
+    /* symbols of the firmware are the base namespace, `allow` limits what modules can see */
+    pub fn import_symbols(
+        &mut self,
+        elf_file: &ELFFile,
+        allow: Option<&[&str]>,
+    ) -> Result<usize, LoadError> {
+        let syms = elf_file
+            .get_exported_symbols()
+            .into_iter()
+            .filter(|(name, _)| allow.map_or(true, |allow| allow.contains(name)))
+            .collect::<Vec<_>>();
+        /* nothing is imported on conflict */
+        if let Some(&(name, _)) = syms.iter().find(|&&(name, addr)| {
+            matches!(self.find_host_symbol(name), Some(exaddr) if exaddr as usize != addr)
+        }) {
+            return Err(LoadError::SymbolConflict(name.to_string()));
+        }
+        syms.iter().for_each(|&(name, addr)| {
+            self.host_symbols
+                .insert(name.to_string(), addr as *const u8);
+        });
+        Ok(syms.len())
+    }
+
+    pub fn register_symbol(&mut self, name: &str, addr: *const u8) -> Result<(), LoadError> {
+        match self.find_host_symbol(name) {
+            Some(exaddr) if exaddr != addr => Err(LoadError::SymbolConflict(name.to_string())),
+            _ => {
+                self.host_symbols.insert(name.to_string(), addr);
+                Ok(())
+            }
+        }
+    }
+
+    /* host symbols come first, they and resolved ones have no module to depend on */
     fn find_symbol_and_weak(
         &self,
         name: &str,
     ) -> Option<(*const u8, rc::Weak<RefCell<ElfModule>>)> {
-        self.modules.iter().find_map(|m| {
-            m.borrow()
-                .find_symbol(name)
-                .and_then(|symaddr| Some((symaddr, rc::Rc::downgrade(m))))
-        })
+        self.find_host_symbol(name)
+            .map(|symaddr| (symaddr, rc::Weak::default()))
+            .or_else(|| self.find_module_symbol(name, true))
+            .or_else(|| self.find_module_symbol(name, false))
+            .or_else(|| {
+                let symaddr = self.resolver.as_ref()?.resolve(name)?;
+                Some((symaddr, rc::Weak::default()))
+            })
     }
 }
 
@@ -128,25 +551,53 @@ impl ElfModuleRoot {
 pub struct ElfModule {
     pub dependents: Vec<rc::Rc<RefCell<Self>>>,
     pub dependencies: Vec<rc::Weak<RefCell<Self>>>,
-    pub text_info: Option<(*mut u8, Layout)>,
-    pub data_info: Option<(*mut u8, Layout)>,
-    pub symbol_info: BTreeMap<&'static str, *const u8>,
+    /* memory of each region used by this module */
+    pub region_allocs: Vec<RegionAlloc>,
+    /* where each ALLOC section ended up */
+    pub placements: Vec<SectionPlacement>,
+    pub debug_info: Option<(*mut u8, Layout)>,
+    pub symbol_info: BTreeMap<&'static str, SymbolDef>,
+    /* named FUNC/OBJECT definitions of any binding sorted by address, for reverse lookup */
+    pub symbol_table: Vec<(CString, SymbolDef)>,
+    /* loaded address of each section, 0 if not loaded */
+    pub section_addrs: Vec<usize>,
+    /* RELA sections not applied since their target isn't loaded */
+    pub skipped_relas: Vec<CString>,
+    /* global offset table for GOT_HI20 */
+    pub got: Vec<usize>,
+    /* resolved value of each symbol, keyed by symtab index, only used while loading */
+    symbol_values: BTreeMap<usize, Vec<usize>>,
+    /* resolved undefined symbols, only used while loading */
+    imports: BTreeMap<String, *const u8>,
+    /* COMMON symbols allocated in data, only used while loading */
+    commons: BTreeMap<String, usize>,
+    /* constructors and destructors in calling order */
+    pub init_funcs: Vec<usize>,
+    pub fini_funcs: Vec<usize>,
+    /* module_init and module_exit, exit is called only if init succeeded */
+    pub init_func: Option<usize>,
+    pub exit_func: Option<usize>,
+    pub initialized: bool,
+    /* region_allocs are W^X by MemoryProtector */
+    pub protected: bool,
 }
 
 impl Drop for ElfModule {
     fn drop(&mut self) {
         // free memory
         // `str` in symbol_info
-        // `[u8]` in text_info & data_info
+        // `[u8]` in region_allocs & debug_info
         self.symbol_info
             .iter()
             .map(|(&n, _)| unsafe {
                 Box::from_raw(ptr::addr_of!(*n) as *mut str);
             })
             .count();
-        self.text_info
-            .and_then(|(p, l)| unsafe { Some(dealloc(p, l)) });
-        self.data_info
+        self.region_allocs
+            .iter()
+            .map(|ra| unsafe { ra.free() })
+            .count();
+        self.debug_info
             .and_then(|(p, l)| unsafe { Some(dealloc(p, l)) });
     }
 }
@@ -157,286 +608,665 @@ impl ElfModule {
         Self {
             dependents: Vec::new(),
             dependencies: Vec::new(),
-            text_info: None,
-            data_info: None,
+            region_allocs: Vec::new(),
+            placements: Vec::new(),
+            debug_info: None,
             symbol_info: BTreeMap::new(),
+            symbol_table: Vec::new(),
+            section_addrs: Vec::new(),
+            skipped_relas: Vec::new(),
+            got: Vec::new(),
+            symbol_values: BTreeMap::new(),
+            imports: BTreeMap::new(),
+            commons: BTreeMap::new(),
+            init_funcs: Vec::new(),
+            fini_funcs: Vec::new(),
+            init_func: None,
+            exit_func: None,
+            initialized: false,
+            protected: false,
         }
     }
 
-    pub fn alloc_memory_with(self, elf_file: &ELFFile) -> Self {
-        let (text, data) = {
-            /* get needed size for allocation */
-            let ((text_size, text_align), (data_size, data_align)) =
-                elf_file.calculate_needed_size();
-            /* allocate memory to load text and data */
-            fn get_layout_and_alloc_memory(s: usize, a: usize) -> Option<(*mut u8, Layout)> {
-                Layout::from_size_align(s, a)
-                    .ok()
-                    .filter(|l| l.size() > 0)
-                    .and_then(|l| {
-                        let p = unsafe { alloc_zeroed(l) };
-                        Some((p, l))
-                    })
-            }
-            // fn dealloc_memory_by_layout(p: *mut u8, l: Layout) {
-            //     unsafe { dealloc(p, l) }
-            // }
-            (
-                get_layout_and_alloc_memory(text_size, text_align),
-                get_layout_and_alloc_memory(data_size, data_align),
-            )
-        };
+    pub fn alloc_memory_with(
+        self,
+        elf_file: &ELFFile,
+        commons: &[(&str, &Symbol)],
+        placement: &Placement,
+    ) -> Result<Self, LoadError> {
+        /* sizes are untrusted, none of them may wrap */
+        fn alignup(v: usize, a: usize) -> Result<usize, LoadError> {
+            v.checked_add((v as *const u8).align_offset(a))
+                .ok_or(LoadError::LayoutInvalid(v, a))
+        }
+        /* size, align and flags of each block, writable sections get their own block
+         * in each region so that W^X can be applied to blocks */
+        let mut blocks = BTreeMap::<(usize, usize), (usize, usize, usize)>::new();
+        let mut place =
+            |region: usize, size: usize, align: usize, flags: usize| -> Result<_, LoadError> {
+                let (cur, max_align, all_flags) = blocks
+                    .entry((region, flags & (SHFlags::WRITE as usize)))
+                    .or_insert((0, 1, 0));
+                let offset = alignup(*cur, align)?;
+                *cur = offset
+                    .checked_add(size)
+                    .ok_or(LoadError::LayoutInvalid(size, align))?;
+                *max_align = (*max_align).max(align);
+                *all_flags |= flags;
+                Ok(offset)
+            };
+        /* section with alloc flag need to be loaded, offset in its region */
+        let sec_offsets = elf_file
+            .section_headers()
+            .iter()
+            .enumerate()
+            .filter(|&(_, sh)| sh.sh_flags & (SHFlags::ALLOC as usize) != 0)
+            .map(|(idx, sh)| {
+                let region = placement.region_of(elf_file.section_name(sh), sh.sh_flags);
+                let offset = place(
+                    region,
+                    alignup(sh.sh_size, 4)?,
+                    sh.sh_addralign.max(1),
+                    sh.sh_flags,
+                )?;
+                Ok((idx, sh, region, sh.sh_flags, offset))
+            })
+            .collect::<Result<Vec<_>, LoadError>>()?;
+        /* COMMON symbols are placed as a writable section named COMMON,
+         * alignments are checked in parse */
+        let common_flags = SHFlags::ALLOC as usize | SHFlags::WRITE as usize;
+        let common_region = placement.region_of("COMMON", common_flags);
+        let common_offsets = commons
+            .iter()
+            .map(|&(name, s)| {
+                Ok((
+                    name,
+                    place(common_region, s.st_size, s.st_value, common_flags)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, LoadError>>()?;
 
         let mut em = self;
-        text.and_then(|t| {
-            em.text_info.replace(t);
-            Some(println!(
-                "[success]allocate text@{:p} with {}bytes",
-                t.0,
-                t.1.size()
-            ))
-        });
-        data.and_then(|d| {
-            em.data_info.replace(d);
-            Some(println!(
-                "[success]allocate data@{:p} with {}bytes",
-                d.0,
-                d.1.size()
-            ))
-        });
-        // em.print_text_and_data();
-        em
+        /* memory is kept in module at once, so it's freed by drop on failure */
+        blocks
+            .iter()
+            .filter(|&(_, &(size, _, _))| size != 0)
+            .try_for_each(
+                |(&(region, _), &(size, align, flags))| -> Result<_, LoadError> {
+                    let l = Layout::from_size_align(size, align)
+                        .map_err(|_| LoadError::LayoutInvalid(size, align))?;
+                    let ra = placement
+                        .alloc(region, l, flags)
+                        .ok_or(LoadError::OutOfMemory(l))?;
+                    println!(
+                        "[success]allocate region {}@{:p} with {}bytes",
+                        region, ra.ptr, size
+                    );
+                    em.region_allocs.push(ra);
+                    Ok(())
+                },
+            )?;
+        let base = |em: &Self, region: usize, flags: usize| {
+            let write = flags & (SHFlags::WRITE as usize);
+            em.region_allocs
+                .iter()
+                .find(|ra| ra.region == region && ra.flags & (SHFlags::WRITE as usize) == write)
+                .map_or(0, |ra| ra.ptr as usize)
+        };
+        em.section_addrs = vec![0; elf_file.section_headers().len()];
+        sec_offsets
+            .iter()
+            .for_each(|&(idx, sh, region, flags, offset)| {
+                let addr = base(&em, region, flags) + offset;
+                em.section_addrs[idx] = addr;
+                em.placements.push(SectionPlacement {
+                    name: elf_file.section_name(sh).to_string(),
+                    region,
+                    addr,
+                    size: sh.sh_size,
+                })
+            });
+        em.commons = common_offsets
+            .into_iter()
+            .map(|(name, offset)| {
+                (
+                    name.to_string(),
+                    base(&em, common_region, common_flags) + offset,
+                )
+            })
+            .collect();
+        Ok(em)
     }
 
-    pub fn fill_undefined_symbols(
+    pub fn fill_undefined_symbols<'a>(
         self,
-        symbols: impl IntoIterator<Item = (&'static str, (*const u8, rc::Weak<RefCell<ElfModule>>))>,
+        symbols: impl IntoIterator<Item = (&'a str, (*const u8, rc::Weak<RefCell<ElfModule>>))>,
     ) -> Self {
         let mut em = self;
         symbols
             .into_iter()
             .map(|(symname, (symvalue, pm))| {
-                em.symbol_info
-                    .entry(Box::leak(symname.to_string().into_boxed_str()))
-                    .or_insert(symvalue);
-                em.dependencies
-                    .iter()
-                    .all(|p| !pm.ptr_eq(p))
+                em.imports.entry(symname.to_string()).or_insert(symvalue);
+                /* host symbols have no module behind */
+                (pm.strong_count() > 0 && em.dependencies.iter().all(|p| !pm.ptr_eq(p)))
                     .then(|| em.dependencies.push(pm))
             })
             .count();
         em
     }
 
-    pub fn load_into_memory(self, elf_file: &ELFFile) -> Self {
+    pub fn load_into_memory(self, elf_file: &ELFFile) -> Result<Self, LoadError> {
         /* load section data into memory */
         // println!("[trying]Load section data into memory");
-        let shstrtab = &elf_file.section_headers()[elf_file.elf_header().e_shstrndx as usize];
+        let em = self;
         elf_file
             .section_headers()
             .iter()
-            /* section has alloc flag need to be load */
-            .filter(|&sh| sh.sh_flags & (SHFlags::ALLOC as usize) != 0)
-            .fold((0, 0), |tdoff, sh| {
-                fn alignup(v: usize, a: usize) -> usize {
-                    (v as *const u8).align_offset(a) + v
-                }
-
-                let sec_align = sh.sh_addralign;
-                let sec_size = alignup(sh.sh_size, 4);
-
-                let (cur_tdoff, (baseaddr, off)) = match sh.sh_flags & (SHFlags::WRITE as usize) {
-                    // text/rodata section has not write flag
-                    0 => (
-                        (alignup(tdoff.0, sec_align) + sec_size, tdoff.1),
-                        (self.text_info, alignup(tdoff.0, sec_align)),
-                    ),
-                    // data|bss section has write flag
-                    _ => (
-                        (tdoff.0, alignup(tdoff.1, sec_align) + sec_size),
-                        (self.data_info, alignup(tdoff.1, sec_align)),
-                    ),
-                };
-                /* copy datas to memory */
-                baseaddr.and_then(|(base, _)| unsafe {
-                    let secaddr = base.offset(off as isize);
-                    if let SHType::NOBITS = sh.sh_type {
-                        secaddr.write_bytes(0, sh.sh_size)
-                    } else {
-                        intrinsics::copy_nonoverlapping(
-                            elf_file.start_address().offset(sh.sh_offset as isize),
-                            secaddr,
-                            sh.sh_size,
-                        );
-                    }
-                    // update section address to system memory
-                    sh.section_address_set(secaddr as usize);
-
-                    Some(println!(
-                        "{}. offset {:#x} -> {:#x}, size: {}",
-                        crate::cstr2ruststr(
-                            elf_file
-                                .start_address()
-                                .offset(shstrtab.sh_offset as isize)
-                                .offset(sh.sh_name as isize)
-                        ),
-                        sh.sh_offset,
-                        sh.sh_addr,
-                        sh.sh_size
-                    ))
-                });
-                cur_tdoff
-            });
+            .enumerate()
+            /* sections are placed in alloc_memory_with */
+            .filter(|&(idx, sh)| {
+                sh.sh_flags & (SHFlags::ALLOC as usize) != 0 && em.section_addrs[idx] != 0
+            })
+            .try_for_each(|(idx, sh)| -> Result<_, LoadError> {
+                /* copy datas straight from image to memory */
+                elf_file.read_section(sh, unsafe {
+                    slice::from_raw_parts_mut(em.section_addrs[idx] as *mut u8, sh.sh_size)
+                })?;
+                println!(
+                    "{}. offset {:#x} -> {:#x}, size: {}",
+                    elf_file.section_name(sh),
+                    sh.sh_offset,
+                    em.section_addrs[idx],
+                    sh.sh_size
+                );
+                Ok(())
+            })?;
         println!("[success]Load section data into memory");
-        // self.print_text_and_data();
-        self
+        Ok(em)
+    }
+
+    /* non-ALLOC .debug_* sections are packed into one buffer, skipped if not `enable` */
+    pub fn load_debug_with(self, elf_file: &ELFFile, enable: bool) -> Result<Self, LoadError> {
+        let mut em = self;
+        if !enable {
+            return Ok(em);
+        }
+        let debugsecs = elf_file
+            .section_headers()
+            .iter()
+            .enumerate()
+            .filter(|&(_, sh)| sh.sh_flags & (SHFlags::ALLOC as usize) == 0)
+            .filter(|&(_, sh)| elf_file.section_name(sh).starts_with(".debug"))
+            .collect::<Vec<_>>();
+        /* sizes and alignments are untrusted, none of them may wrap */
+        let debug_align = |sh: &SHeader| {
+            sh.sh_addralign
+                .max(1)
+                .checked_next_power_of_two()
+                .ok_or(LoadError::LayoutInvalid(sh.sh_size, sh.sh_addralign))
+        };
+        let (size, offsets) =
+            debugsecs
+                .iter()
+                .try_fold((0usize, Vec::new()), |(size, mut offsets), &(_, sh)| {
+                    let a = debug_align(sh)?;
+                    let offset = size
+                        .checked_add(a - 1)
+                        .ok_or(LoadError::LayoutInvalid(size, a))?
+                        & !(a - 1);
+                    offsets.push(offset);
+                    let end = offset
+                        .checked_add(sh.sh_size)
+                        .ok_or(LoadError::LayoutInvalid(sh.sh_size, a))?;
+                    Ok::<_, LoadError>((end, offsets))
+                })?;
+        if size == 0 {
+            return Ok(em);
+        }
+        let align = debugsecs.iter().try_fold(1, |align, &(_, sh)| {
+            Ok::<_, LoadError>(align.max(debug_align(sh)?))
+        })?;
+        let l = Layout::from_size_align(size, align)
+            .map_err(|_| LoadError::LayoutInvalid(size, align))?;
+        let p = unsafe { alloc_zeroed(l) };
+        if p.is_null() {
+            return Err(LoadError::OutOfMemory(l));
+        }
+        em.debug_info = Some((p, l));
+        debugsecs.iter().zip(offsets).try_for_each(
+            |(&(idx, sh), offset)| -> Result<(), LoadError> {
+                let secaddr = unsafe { p.add(offset) };
+                elf_file.read_section(sh, unsafe {
+                    slice::from_raw_parts_mut(secaddr, sh.sh_size)
+                })?;
+                em.section_addrs[idx] = secaddr as usize;
+                println!(
+                    "{}. debug -> {:#x}",
+                    elf_file.section_name(sh),
+                    secaddr as usize
+                );
+                Ok(())
+            },
+        )?;
+        println!("[success]allocate debug@{:p} with {}bytes", p, size);
+        Ok(em)
     }
 
-    pub fn update_symbol_value_with(self, elf_file: &ELFFile) -> Self {
+    pub fn update_symbol_value_with(self, elf_file: &ELFFile) -> Result<Self, LoadError> {
         /* update symbol value */
         // println!("[trying]update symbol value");
+        let mut em = self;
         elf_file
             .section_headers()
             .iter()
-            .filter(|&sh| matches!(sh.section_type(), SHType::SYMTAB))
-            .flat_map(|sh| {
-                let strtab = &elf_file.section_headers()[sh.sh_link as usize];
-                let symbols = unsafe {
-                    slice::from_raw_parts_mut(
-                        elf_file.start_address().offset(sh.sh_offset as isize) as *mut Symbol,
-                        sh.sh_size / sh.sh_entsize,
-                    )
-                };
-                symbols.iter_mut().filter(|s| s.st_name != 0).map(|s| {
-                    let symname = unsafe {
-                        crate::cstr2ruststr(
-                            elf_file
-                                .start_address()
-                                .offset(strtab.sh_offset as isize)
-                                .offset(s.st_name as isize),
-                        )
-                    };
-                    // SHN_UNDEF 	    0
-                    // SHN_LORESERVE    0xff00
-                    // SHN_LOPROC 	    0xff00
-                    // SHN_HIPROC 	    0xff1f
-                    // SHN_LOOS 	    0xff20
-                    // SHN_HIOS 	    0xff3f
-                    // SHN_ABS 	        0xfff1
-                    // SHN_COMMON 	    0xfff2
-                    // SHN_XINDEX 	    0xffff
-                    // SHN_HIRESERVE 	0xffff
-                    match s.st_shndx {
-                        0xfff2 => {
-                            panic!("Re-compile with -fno-common");
-                        }
-                        0x0 => {
-                            print!("SHN_UNDEF: name={} {:#x}", symname, s.st_value);
-                            self.find_symbol(symname).and_then(|exsym| {
-                                s.symbol_value_set(exsym as usize);
-                                Some(print!("->{:#x}", s.st_value))
-                            });
-                            println!()
-                        }
-                        secidx => {
-                            if secidx == 0xfff1 {
-                                println!("SHN_ABS: st_value={:#x}", s.st_value)
-                            } else {
-                                let secbase = elf_file.section_headers()[secidx as usize].sh_addr;
-                                println!(
-                                    "Other: {:#x}+{:#x}={:#x}",
-                                    s.st_value,
-                                    secbase,
-                                    s.st_value + secbase
-                                );
-                                s.symbol_value_set(s.st_value + secbase);
-                            }
-                            if let STBind::GLOBAL = s.symbol_bind() {
-                                self.add_symbol(
-                                    Box::leak(symname.to_string().into_boxed_str()),
-                                    s.st_value,
-                                );
-                            }
-                        }
-                    }
-                    // println!("    {:?}", s);
-                })
-            })
-            .count();
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::SYMTAB))
+            .try_for_each(|(symtabidx, sh)| -> Result<(), LoadError> {
+                let values = elf_file
+                    .symbols(symtabidx)
+                    .iter()
+                    .map(|s| em.resolve_symbol_value(elf_file, sh, s))
+                    .collect::<Result<Vec<_>, LoadError>>()?;
+                em.symbol_values.insert(symtabidx, values);
+                Ok(())
+            })?;
+        em.symbol_table.sort_by_key(|(_, def)| def.addr);
         println!("[success]update symbol value");
-        self
+        Ok(em)
+    }
+
+    fn resolve_symbol_value(
+        &mut self,
+        elf_file: &ELFFile,
+        symtab: &SHeader,
+        s: &Symbol,
+    ) -> Result<usize, LoadError> {
+        let symname = elf_file.symbol_name(symtab, s);
+        // SHN_UNDEF 	    0
+        // SHN_LORESERVE    0xff00
+        // SHN_LOPROC 	    0xff00
+        // SHN_HIPROC 	    0xff1f
+        // SHN_LOOS 	    0xff20
+        // SHN_HIOS 	    0xff3f
+        // SHN_ABS 	        0xfff1
+        // SHN_COMMON 	    0xfff2
+        // SHN_XINDEX 	    0xffff
+        // SHN_HIRESERVE 	0xffff
+        let value = match s.st_shndx {
+            0xfff2 => match (self.imports.get(symname), self.commons.get(symname)) {
+                /* merged with an existing definition */
+                (Some(&exsym), _) => return Ok(exsym as usize),
+                (_, Some(&addr)) => {
+                    println!("SHN_COMMON: name={} ->{:#x}", symname, addr);
+                    addr
+                }
+                _ => return Err(LoadError::CommonSymbol(symname.to_string())),
+            },
+            0x0 if s.st_name == 0 => return Ok(0),
+            0x0 => {
+                print!("SHN_UNDEF: name={} {:#x}", symname, s.st_value);
+                let exsym = match (self.imports.get(symname), s.symbol_bind()) {
+                    (Some(&exsym), _) => exsym,
+                    /* undefined weak is left to be 0 */
+                    (None, STBind::WEAK) => ptr::null(),
+                    (None, _) => return Err(LoadError::UndefinedSymbol(symname.to_string())),
+                };
+                println!("->{:#x}", exsym as usize);
+                return Ok(exsym as usize);
+            }
+            0xfff1 => {
+                println!("SHN_ABS: st_value={:#x}", s.st_value);
+                s.st_value
+            }
+            secidx => {
+                /* non-ALLOC sections such as .debug_* have no address at runtime,
+                 * references to their symbols are section offsets as a linker makes */
+                let secbase = match elf_file.section_headers()[secidx as usize].sh_flags
+                    & (SHFlags::ALLOC as usize)
+                {
+                    0 => 0,
+                    _ => self.section_addrs[secidx as usize],
+                };
+                println!(
+                    "Other: {:#x}+{:#x}={:#x}",
+                    s.st_value,
+                    secbase,
+                    s.st_value.wrapping_add(secbase)
+                );
+                if secbase != 0
+                    && s.st_name != 0
+                    && matches!(s.symbol_type(), STType::FUNC | STType::OBJECT)
+                {
+                    self.symbol_table.push((
+                        CString::new(symname).unwrap_or_default(),
+                        SymbolDef::new(s, s.st_value.wrapping_add(secbase)),
+                    ));
+                }
+                s.st_value.wrapping_add(secbase)
+            }
+        };
+        if s.st_name != 0 && s.is_exported() && !MODULE_ENTRIES.contains(&symname) {
+            let def = SymbolDef::new(s, value);
+            self.add_symbol(Box::leak(symname.to_string().into_boxed_str()), def);
+        }
+        // println!("    {:?}", s);
+        Ok(value)
     }
 
-    pub fn relocate_symbols_with(self, elf_file: &ELFFile) -> Self {
+    pub fn relocate_symbols_with(self, elf_file: &ELFFile) -> Result<Self, LoadError> {
         /* relocate text and data */
         // println!("[trying]relocate text and data");
-        elf_file
+        let mut em = self;
+        let got_slots = em.alloc_got_with(elf_file)?;
+        let symval_of = |em: &Self, symtabidx: usize, r: &Rela| match r.rela_type() {
+            /* refer to the GOT slot instead of the symbol */
+            RelaType::GOT_HI20 => {
+                ptr::addr_of!(em.got[got_slots[&(symtabidx, r.symbol_offset())]]) as usize
+            }
+            _ => em.symbol_values[&symtabidx][r.symbol_offset()],
+        };
+        let (relasecs, skipped): (Vec<_>, Vec<_>) = elf_file
             .section_headers()
             .iter()
-            .filter(|&sh| matches!(sh.section_type(), SHType::RELA))
-            .map(|relasec| {
-                let symsec = &elf_file.section_headers()[relasec.sh_link as usize];
-                let dstsec = &elf_file.section_headers()[relasec.sh_info as usize];
-                let dstsecbase = dstsec.sh_addr;
-                let (relas, symbols) = unsafe {
-                    (
-                        slice::from_raw_parts(
-                            elf_file.start_address().offset(relasec.sh_offset as isize)
-                                as *const Rela,
-                            relasec.sh_size / relasec.sh_entsize,
-                        ),
-                        slice::from_raw_parts(
-                            elf_file.start_address().offset(symsec.sh_offset as isize)
-                                as *const Symbol,
-                            symsec.sh_size / symsec.sh_entsize,
-                        ),
-                    )
-                };
-
-                relas
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::RELA))
+            .partition(|&(_, sh)| em.section_addrs[sh.sh_info as usize] != 0);
+        /* target isn't in memory, relocating it would write to low addresses */
+        em.skipped_relas = skipped
+            .iter()
+            .map(|&(_, sh)| {
+                println!(
+                    "[skipped]{} targets {} which isn't loaded",
+                    elf_file.section_name(sh),
+                    elf_file.section_name(&elf_file.section_headers()[sh.sh_info as usize])
+                );
+                CString::new(elf_file.section_name(sh)).unwrap_or_default()
+            })
+            .collect();
+        /* values of all HI20 first, PCREL_LO12 may come before its HI20 or in other section */
+        let emref = &em;
+        let hi20_values = relasecs
+            .iter()
+            .flat_map(|&(relaidx, relasec)| {
+                let dstsecbase = emref.section_addrs[relasec.sh_info as usize];
+                elf_file
+                    .relas(relaidx)
                     .iter()
-                    .map(|r| {
-                        let sym = &symbols[r.symbol_offset()];
+                    .filter(|r| matches!(r.rela_type(), RelaType::PCREL_HI20 | RelaType::GOT_HI20))
+                    .map(move |r| {
                         let addr = dstsecbase + r.r_offset;
-                        unsafe {
-                            // real relocate
-                            ELFFile::relocateadd(r, sym, addr as *mut u8);
-                        }
+                        let symval = symval_of(emref, relasec.sh_link as usize, r);
+                        (addr, ELFFile::pcrel_hi20_value(r, symval, addr))
                     })
-                    .count();
             })
-            .count();
+            .collect::<BTreeMap<_, _>>();
+        relasecs.iter().try_for_each(|&(relaidx, relasec)| {
+            let dstsec = &elf_file.section_headers()[relasec.sh_info as usize];
+            let dstsecbase = em.section_addrs[relasec.sh_info as usize];
+
+            elf_file.relas(relaidx).iter().try_for_each(|r| {
+                let symval = symval_of(&em, relasec.sh_link as usize, r);
+                unsafe {
+                    let place = slice::from_raw_parts_mut(
+                        (dstsecbase + r.r_offset) as *mut u8,
+                        dstsec.sh_size - r.r_offset,
+                    );
+                    // real relocate
+                    ELFFile::relocateadd(r, symval, place, &hi20_values)
+                }
+                .map_err(|err| match err {
+                    RelocateError::Overflow(rtype, _) => LoadError::RelocationOverflow(
+                        rtype,
+                        elf_file.section_name(dstsec).to_string(),
+                        r.r_offset,
+                        Self::rela_symbol_name(elf_file, relasec.sh_link as usize, r).to_string(),
+                    ),
+                    _ => LoadError::Relocation(err),
+                })
+            })
+        })?;
+        /* resolved values are not needed after relocation */
+        em.symbol_values.clear();
+        em.imports.clear();
+        em.commons.clear();
         println!("[success]relocate text and data");
-        self
+        Ok(em)
     }
 
-    fn add_symbol(&self, name: &'static str, sym: usize) {
-        unsafe { &mut *(ptr::addr_of!(*self) as *mut Self) }
-            .symbol_info
-            .entry(name)
-            .or_insert(sym as *const u8);
+    /* section symbols are named by their section */
+    fn rela_symbol_name<'a>(elf_file: &'a ELFFile, symtabidx: usize, r: &Rela) -> &'a str {
+        let symtab = &elf_file.section_headers()[symtabidx];
+        let sym = &elf_file.symbols(symtabidx)[r.symbol_offset()];
+        match elf_file.section_headers().get(sym.st_shndx as usize) {
+            Some(sh) if sym.st_name == 0 => elf_file.section_name(sh),
+            _ => elf_file.symbol_name(symtab, sym),
+        }
     }
 
-    pub fn find_symbol(&self, name: &str) -> Option<*const u8> {
-        self.symbol_info.get(&name).and_then(|p| Some(*p))
+    pub fn collect_init_fini_with(self, elf_file: &ELFFile) -> Self {
+        let mut em = self;
+        /* .preinit_array and .init_array run forward, .ctors backward as crtbegin does */
+        em.init_funcs = [
+            em.array_entries(elf_file, ".preinit_array", false),
+            em.array_entries(elf_file, ".init_array", false),
+            em.array_entries(elf_file, ".ctors", true),
+        ]
+        .concat();
+        /* .fini_array runs backward, .dtors forward as crtbegin does */
+        em.fini_funcs = [
+            em.array_entries(elf_file, ".fini_array", true),
+            em.array_entries(elf_file, ".dtors", false),
+        ]
+        .concat();
+        /* by symbol of any binding, or by pointer in a dedicated section */
+        em.init_func = em.entry_func("module_init").or_else(|| {
+            em.array_entries(elf_file, ".module_init", false)
+                .first()
+                .copied()
+        });
+        em.exit_func = em.entry_func("module_exit").or_else(|| {
+            em.array_entries(elf_file, ".module_exit", false)
+                .first()
+                .copied()
+        });
+        em
+    }
+
+    fn entry_func(&self, name: &str) -> Option<usize> {
+        self.symbol_table
+            .iter()
+            .find(|(n, def)| {
+                n.as_bytes() == name.as_bytes() && matches!(def.symbol_type(), STType::FUNC)
+            })
+            .map(|(_, def)| def.addr as usize)
+    }
+
+    /* relocated entries of `prefix` and `prefix.NNNNN` sections in linking order,
+     * prioritized ones are sorted first, 0 and -1 are not functions */
+    fn array_entries(&self, elf_file: &ELFFile, prefix: &str, reverse: bool) -> Vec<usize> {
+        let mut secs = elf_file
+            .section_headers()
+            .iter()
+            .enumerate()
+            .filter(|&(idx, _)| self.section_addrs[idx] != 0)
+            .filter_map(|(idx, sh)| {
+                let suffix = elf_file.section_name(sh).strip_prefix(prefix)?;
+                let priority = match suffix {
+                    "" => u32::MAX,
+                    _ => suffix.strip_prefix('.')?.parse::<u32>().ok()?,
+                };
+                Some((priority, idx, sh))
+            })
+            .collect::<Vec<_>>();
+        secs.sort_by_key(|&(priority, idx, _)| (priority, idx));
+        let mut entries = secs
+            .iter()
+            .flat_map(|&(_, idx, sh)| unsafe {
+                slice::from_raw_parts(
+                    self.section_addrs[idx] as *const usize,
+                    sh.sh_size / mem::size_of::<usize>(),
+                )
+            })
+            .copied()
+            .filter(|&f| f != 0 && f != usize::MAX)
+            .collect::<Vec<_>>();
+        if reverse {
+            entries.reverse();
+        }
+        entries
     }
 
-    fn print_text_and_data(&self) {
-        fn _print_info(p: *mut u8, s: usize) {
-            let s = unsafe { core::slice::from_raw_parts(p, s) };
-            crate::hex_dump(s)
+    pub fn sync_icache_with(self, cache_sync: Option<&dyn CacheSync>) -> Self {
+        self.sync_icache(cache_sync);
+        self
+    }
+
+    /* regions with code only, fence.i covers all of them at once */
+    fn sync_icache(&self, cache_sync: Option<&dyn CacheSync>) {
+        match cache_sync {
+            Some(cache_sync) => self
+                .region_allocs
+                .iter()
+                .filter(|ra| ra.flags & (SHFlags::EXECINSTR as usize) != 0)
+                .for_each(|ra| cache_sync.sync(ra.ptr, ra.layout.size())),
+            None => fence_i(),
         }
-        self.text_info
-            .and_then(|(p, l)| {
-                println!("text@{:p} with {}bytes", p, l.size());
-                Some(_print_info(p, l.size()))
+    }
+
+    /* text and rodata become RX or R, data stays RW, all reverted on failure,
+     * blocks in heap share pages with other objects and are left alone */
+    pub fn protect_with(self, protector: Option<&dyn MemoryProtector>) -> Result<Self, LoadError> {
+        let mut em = self;
+        let protector = match protector {
+            Some(protector) => protector,
+            None => return Ok(em),
+        };
+        let blocks = em
+            .region_allocs
+            .iter()
+            .filter(|ra| !ra.in_heap())
+            .collect::<Vec<_>>();
+        /* a section both writable and executable can't be W^X */
+        let failed = blocks.iter().position(|ra| {
+            !ra.prot().map_or(false, |prot| {
+                protector.protect(ra.ptr, ra.layout.size(), prot)
             })
-            .unwrap_or_else(|| println!("this module has no text"));
-        self.data_info
-            .and_then(|(p, l)| {
-                println!("data@{:p} with {}bytes", p, l.size());
-                Some(_print_info(p, l.size()))
+        });
+        if let Some(idx) = failed {
+            println!("[failed]protect region {}", blocks[idx].region);
+            blocks[..idx].iter().for_each(|ra| {
+                protector.protect(ra.ptr, ra.layout.size(), PROT_READ | PROT_WRITE);
+            });
+            return Err(LoadError::ProtectFailed(blocks[idx].region));
+        }
+        em.protected = true;
+        Ok(em)
+    }
+
+    pub fn unprotect_with(&mut self, protector: &dyn MemoryProtector) {
+        if !mem::take(&mut self.protected) {
+            return;
+        }
+        self.region_allocs
+            .iter()
+            .filter(|ra| !ra.in_heap())
+            .for_each(|ra| {
+                protector.protect(ra.ptr, ra.layout.size(), PROT_READ | PROT_WRITE);
+            });
+    }
+
+    unsafe fn call_funcs(funcs: &[usize]) {
+        funcs
+            .iter()
+            .for_each(|&f| mem::transmute::<usize, extern "C" fn()>(f)());
+    }
+
+    /* one GOT slot for each symbol referred by GOT_HI20, returns slot of (symtab, symbol) */
+    fn alloc_got_with(
+        &mut self,
+        elf_file: &ELFFile,
+    ) -> Result<BTreeMap<(usize, usize), usize>, LoadError> {
+        let got_slots = elf_file
+            .section_headers()
+            .iter()
+            .enumerate()
+            .filter(|&(_, sh)| matches!(sh.section_type(), SHType::RELA))
+            .flat_map(|(relaidx, relasec)| {
+                elf_file
+                    .relas(relaidx)
+                    .iter()
+                    .filter(|r| matches!(r.rela_type(), RelaType::GOT_HI20))
+                    .map(move |r| (relasec.sh_link as usize, r.symbol_offset()))
             })
-            .unwrap_or_else(|| println!("this module has no data"));
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .enumerate()
+            .map(|(slot, sym)| (sym, slot))
+            .collect::<BTreeMap<_, _>>();
+        /* GOT is never reallocated after this, so the slots stay in place */
+        self.got.try_reserve_exact(got_slots.len()).map_err(|_| {
+            LoadError::OutOfMemory(Layout::array::<usize>(got_slots.len()).unwrap())
+        })?;
+        self.got.resize(got_slots.len(), 0);
+        got_slots.iter().for_each(|(&(symtabidx, symidx), &slot)| {
+            self.got[slot] = self.symbol_values[&symtabidx][symidx]
+        });
+        Ok(got_slots)
+    }
+
+    fn add_symbol(&mut self, name: &'static str, def: SymbolDef) {
+        self.symbol_info.entry(name).or_insert(def);
+    }
+
+    pub fn find_symbol(&self, name: &str) -> Option<*const u8> {
+        self.symbol_info.get(&name).map(|def| def.addr)
+    }
+
+    pub fn find_symbol_info(&self, name: &str) -> Option<SymbolInfo> {
+        self.symbol_info
+            .get(&name)
+            .map(|def| def.info(self as *const Self))
+    }
+
+    fn addr_info(&self, addr: *const u8) -> Option<AddrInfo> {
+        let ra = self
+            .region_allocs
+            .iter()
+            .find(|ra| ra.contains(addr as usize))?;
+        /* a symbol of a lower block isn't the one `addr` lies in */
+        let nearest = self
+            .symbol_table
+            .partition_point(|(_, def)| def.addr <= addr)
+            .checked_sub(1)
+            .map(|idx| &self.symbol_table[idx])
+            .filter(|(_, def)| ra.contains(def.addr as usize));
+        Some(AddrInfo {
+            module: self as *const Self,
+            region: ra.region,
+            symbol: nearest.map_or(ptr::null(), |(name, _)| name.as_ptr() as *const u8),
+            symbol_addr: nearest.map_or(ptr::null(), |(_, def)| def.addr),
+            offset: addr as usize - nearest.map_or(addr as usize, |(_, def)| def.addr as usize),
+        })
+    }
+
+    fn print_regions(&self) {
+        if self.region_allocs.is_empty() {
+            println!("this module has nothing to load");
+        }
+        self.region_allocs.iter().for_each(|ra| {
+            println!(
+                "region {}@{:p} with {}bytes",
+                ra.region,
+                ra.ptr,
+                ra.layout.size()
+            );
+            crate::hex_dump(unsafe { slice::from_raw_parts(ra.ptr, ra.layout.size()) })
+        });
+        self.placements.iter().for_each(|sp| {
+            println!(
+                "{} -> region {}@{:#x}, size: {}",
+                sp.name, sp.region, sp.addr, sp.size
+            )
+        });
     }
 }
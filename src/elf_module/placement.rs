@@ -0,0 +1,274 @@
+use alloc::alloc::{alloc, dealloc, Layout};
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug};
+use core::ptr;
+
+use super::{PROT_EXEC, PROT_READ, PROT_WRITE};
+use crate::elf::headers::SHFlags;
+
+/* default regions in heap, custom ones are numbered after them */
+pub const REGION_TEXT: usize = 0;
+pub const REGION_DATA: usize = 1;
+
+/* memory such as ITCM, IRAM or PSRAM which sections can be placed in */
+pub trait RegionAllocator {
+    /* aligned to `layout`, null if out of memory */
+    fn alloc(&self, layout: Layout) -> *mut u8;
+    fn dealloc(&self, ptr: *mut u8, layout: Layout);
+}
+
+impl Debug for dyn RegionAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RegionAllocator")
+    }
+}
+
+struct HeapRegion;
+
+impl RegionAllocator for HeapRegion {
+    fn alloc(&self, layout: Layout) -> *mut u8 {
+        unsafe { alloc(layout) }
+    }
+
+    fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { dealloc(ptr, layout) }
+    }
+}
+
+static HEAP_REGION: HeapRegion = HeapRegion;
+
+/* a section goes to `region` if its name matches `pattern` and `sh_flags & flags_mask == flags`,
+ * `pattern` may end with `*` to match any suffix, COMMON symbols are named "COMMON" */
+#[derive(Debug, Clone)]
+pub struct PlacementRule {
+    pub pattern: String,
+    pub flags_mask: usize,
+    pub flags: usize,
+    pub region: usize,
+}
+
+impl PlacementRule {
+    fn matches(&self, name: &str, flags: usize) -> bool {
+        let name_matched = match self.pattern.strip_suffix('*') {
+            Some(prefix) => name.starts_with(prefix),
+            None => name == self.pattern,
+        };
+        name_matched && flags & self.flags_mask == self.flags
+    }
+}
+
+/* the first matched rule wins, sections with WRITE go to data and others to text by default */
+#[derive(Debug)]
+pub struct Placement {
+    pub rules: Vec<PlacementRule>,
+    pub regions: Vec<Box<dyn RegionAllocator>>,
+}
+
+impl Placement {
+    pub const fn new() -> Self {
+        Placement {
+            rules: Vec::new(),
+            regions: Vec::new(),
+        }
+    }
+
+    /* regions are never removed, modules keep pointers to them */
+    pub fn add_region(&mut self, allocator: Box<dyn RegionAllocator>) -> usize {
+        self.regions.push(allocator);
+        REGION_DATA + self.regions.len()
+    }
+
+    pub fn add_rule(&mut self, rule: PlacementRule) -> Option<()> {
+        (rule.region <= REGION_DATA + self.regions.len()).then(|| self.rules.push(rule))
+    }
+
+    pub fn region_of(&self, name: &str, flags: usize) -> usize {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(name, flags))
+            .map_or(
+                match flags & (SHFlags::WRITE as usize) {
+                    0 => REGION_TEXT,
+                    _ => REGION_DATA,
+                },
+                |rule| rule.region,
+            )
+    }
+
+    fn allocator(&self, region: usize) -> *const dyn RegionAllocator {
+        match region.checked_sub(REGION_DATA + 1) {
+            Some(idx) => &*self.regions[idx],
+            None => &HEAP_REGION,
+        }
+    }
+
+    /* zeroed memory of `layout` in `region`, `flags` are of sections placed in it */
+    pub fn alloc(&self, region: usize, layout: Layout, flags: usize) -> Option<RegionAlloc> {
+        let allocator = self.allocator(region);
+        let ptr = unsafe { (*allocator).alloc(layout) };
+        if ptr.is_null() {
+            return None;
+        }
+        unsafe { ptr::write_bytes(ptr, 0, layout.size()) };
+        Some(RegionAlloc {
+            region,
+            ptr,
+            layout,
+            flags,
+            allocator,
+        })
+    }
+}
+
+/* memory of a module in one region, freed by the module */
+#[derive(Debug, Clone, Copy)]
+pub struct RegionAlloc {
+    pub region: usize,
+    pub ptr: *mut u8,
+    pub layout: Layout,
+    pub flags: usize,
+    allocator: *const dyn RegionAllocator,
+}
+
+impl RegionAlloc {
+    pub fn contains(&self, addr: usize) -> bool {
+        (self.ptr as usize..self.ptr as usize + self.layout.size()).contains(&addr)
+    }
+
+    /* REGION_TEXT and REGION_DATA */
+    pub fn in_heap(&self) -> bool {
+        self.region <= REGION_DATA
+    }
+
+    /* W^X of sections placed in it, None if it needs both */
+    pub fn prot(&self) -> Option<u32> {
+        match (
+            self.flags & (SHFlags::WRITE as usize),
+            self.flags & (SHFlags::EXECINSTR as usize),
+        ) {
+            (0, 0) => Some(PROT_READ),
+            (0, _) => Some(PROT_READ | PROT_EXEC),
+            (_, 0) => Some(PROT_READ | PROT_WRITE),
+            _ => None,
+        }
+    }
+
+    pub unsafe fn free(&self) {
+        (*self.allocator).dealloc(self.ptr, self.layout)
+    }
+}
+
+/* where a loaded section ended up */
+#[derive(Debug, Clone)]
+pub struct SectionPlacement {
+    pub name: String,
+    pub region: usize,
+    pub addr: usize,
+    pub size: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use core::slice;
+
+    const WRITE: usize = SHFlags::WRITE as usize;
+    const ALLOC: usize = SHFlags::ALLOC as usize;
+    const EXEC: usize = SHFlags::EXECINSTR as usize;
+
+    fn rule(pattern: &str, flags_mask: usize, flags: usize, region: usize) -> PlacementRule {
+        PlacementRule {
+            pattern: pattern.to_string(),
+            flags_mask,
+            flags,
+            region,
+        }
+    }
+
+    #[test]
+    fn rule_matches_name_and_flags() {
+        let exact = rule(".text", 0, 0, REGION_TEXT);
+        assert!(exact.matches(".text", ALLOC | EXEC));
+        assert!(!exact.matches(".text.fast", ALLOC | EXEC));
+        let prefix = rule(".text.fast*", EXEC, EXEC, REGION_TEXT);
+        assert!(prefix.matches(".text.fast", ALLOC | EXEC));
+        assert!(prefix.matches(".text.fast.foo", ALLOC | EXEC));
+        assert!(!prefix.matches(".text.fast.foo", ALLOC));
+        assert!(!prefix.matches(".text", ALLOC | EXEC));
+        assert!(rule("*", WRITE, 0, REGION_TEXT).matches(".rodata", ALLOC));
+    }
+
+    #[test]
+    fn region_of_defaults_and_first_match() {
+        let mut placement = Placement::new();
+        assert_eq!(placement.region_of(".text", ALLOC | EXEC), REGION_TEXT);
+        assert_eq!(placement.region_of(".rodata", ALLOC), REGION_TEXT);
+        assert_eq!(placement.region_of(".bss", ALLOC | WRITE), REGION_DATA);
+        let fast = placement.add_region(Box::new(HeapRegion));
+        let psram = placement.add_region(Box::new(HeapRegion));
+        assert_eq!((fast, psram), (2, 3));
+        placement.add_rule(rule(".text.fast*", 0, 0, fast)).unwrap();
+        placement.add_rule(rule(".text*", 0, 0, psram)).unwrap();
+        placement.add_rule(rule("COMMON", 0, 0, psram)).unwrap();
+        assert_eq!(placement.region_of(".text.fast.foo", ALLOC | EXEC), fast);
+        assert_eq!(placement.region_of(".text.foo", ALLOC | EXEC), psram);
+        assert_eq!(placement.region_of("COMMON", ALLOC | WRITE), psram);
+        assert_eq!(placement.region_of(".data", ALLOC | WRITE), REGION_DATA);
+    }
+
+    #[test]
+    fn rule_needs_existing_region() {
+        let mut placement = Placement::new();
+        assert!(placement
+            .add_rule(rule(".data", 0, 0, REGION_DATA))
+            .is_some());
+        assert!(placement.add_rule(rule(".data", 0, 0, 2)).is_none());
+        placement.add_region(Box::new(HeapRegion));
+        assert!(placement.add_rule(rule(".data", 0, 0, 2)).is_some());
+        assert_eq!(placement.rules.len(), 2);
+    }
+
+    #[test]
+    fn alloc_is_zeroed_and_in_its_region() {
+        let placement = Placement::new();
+        let layout = Layout::from_size_align(64, 16).unwrap();
+        let ra = placement.alloc(REGION_DATA, layout, ALLOC | WRITE).unwrap();
+        assert_eq!(ra.ptr as usize % 16, 0);
+        assert!(unsafe { slice::from_raw_parts(ra.ptr, 64) }
+            .iter()
+            .all(|&b| b == 0));
+        assert!(ra.contains(ra.ptr as usize + 63));
+        assert!(!ra.contains(ra.ptr as usize + 64));
+        unsafe { ra.free() };
+    }
+
+    #[test]
+    fn prot_is_w_xor_x() {
+        let mut placement = Placement::new();
+        let fast = placement.add_region(Box::new(HeapRegion));
+        let layout = Layout::from_size_align(8, 4).unwrap();
+        let prot = |region, flags| {
+            let ra = placement.alloc(region, layout, flags).unwrap();
+            unsafe { ra.free() };
+            (ra.in_heap(), ra.prot())
+        };
+        assert_eq!(
+            prot(REGION_TEXT, ALLOC | EXEC),
+            (true, Some(PROT_READ | PROT_EXEC))
+        );
+        assert_eq!(prot(REGION_TEXT, ALLOC), (true, Some(PROT_READ)));
+        assert_eq!(
+            prot(REGION_DATA, ALLOC | WRITE),
+            (true, Some(PROT_READ | PROT_WRITE))
+        );
+        assert_eq!(
+            prot(fast, ALLOC | EXEC),
+            (false, Some(PROT_READ | PROT_EXEC))
+        );
+        /* a section both writable and executable */
+        assert_eq!(prot(fast, ALLOC | WRITE | EXEC), (false, None));
+    }
+}
@@ -0,0 +1,59 @@
+use core::slice;
+
+/* same layout as `elf_export_t` in include/elfloader_fcn.h, `name` ends with NUL */
+#[repr(C)]
+pub struct ExportSymbol {
+    pub name: *const u8,
+    pub addr: *const u8,
+}
+
+unsafe impl Sync for ExportSymbol {}
+
+/* put a host symbol into the export table, which is searched while resolving
+ * undefined symbols of loaded modules:
+ *   export_symbol!(memcpy);
+ *   export_symbol!("errno", ptr::addr_of!(ERRNO) as *const u8);
+ */
+#[macro_export]
+macro_rules! export_symbol {
+    ($sym:ident) => {
+        $crate::export_symbol!(stringify!($sym), $sym as *const u8);
+    };
+    ($name:expr, $addr:expr) => {
+        const _: () = {
+            #[used]
+            #[link_section = "elfloader_exports"]
+            static EXPORT: $crate::export::ExportSymbol = $crate::export::ExportSymbol {
+                name: concat!($name, "\0").as_ptr(),
+                addr: $addr,
+            };
+        };
+    };
+}
+
+/* bounds of section `elfloader_exports` provided by linker, null if nothing exported */
+extern "C" {
+    #[linkage = "extern_weak"]
+    static __start_elfloader_exports: *const ExportSymbol;
+    #[linkage = "extern_weak"]
+    static __stop_elfloader_exports: *const ExportSymbol;
+}
+
+fn exports() -> &'static [ExportSymbol] {
+    unsafe {
+        match __start_elfloader_exports.is_null() {
+            true => &[],
+            false => slice::from_raw_parts(
+                __start_elfloader_exports,
+                __stop_elfloader_exports.offset_from(__start_elfloader_exports) as usize,
+            ),
+        }
+    }
+}
+
+pub fn find_export(name: &str) -> Option<*const u8> {
+    exports()
+        .iter()
+        .find(|e| unsafe { crate::cstr2ruststr(e.name) } == name)
+        .map(|e| e.addr)
+}
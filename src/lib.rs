@@ -1,30 +1,59 @@
-#![no_std]
+/* host tests run with std */
+#![cfg_attr(not(test), no_std)]
 #![feature(panic_info_message)]
 #![feature(alloc_error_handler)]
 #![feature(linked_list_remove)]
 #![feature(ptr_const_cast)]
 #![feature(core_intrinsics)]
 #![feature(stdsimd)]
+#![feature(linkage)]
 #![allow(dead_code)]
 
 #[macro_use]
 mod console;
+#[cfg(not(test))]
 mod allocator;
+#[cfg(not(test))]
 mod panic;
 
 mod elf;
 mod elf_module;
+mod export;
 
+use elf::reader::ElfRead;
 use elf::ELFFile;
+use elf::ELFFileError;
+use elf_module::AddrInfo;
+use elf_module::CacheSync;
 use elf_module::ElfModule;
 use elf_module::ElfModuleRoot;
+use elf_module::LoadError;
+use elf_module::LoadErrorCode;
+use elf_module::MemoryProtector;
+use elf_module::PlacementRule;
+use elf_module::RegionAllocator;
+use elf_module::SymbolInfo;
+use elf_module::SymbolResolver;
+
+use alloc::boxed::Box;
+use alloc::ffi::CString;
+use alloc::vec::Vec;
 
 extern crate alloc;
 
 static mut ELF_MODULE_ROOT: ElfModuleRoot = ElfModuleRoot {
     modules: alloc::collections::LinkedList::new(),
+    host_symbols: alloc::collections::BTreeMap::new(),
+    symbol_index: alloc::collections::BTreeMap::new(),
+    resolver: None,
+    load_debug: false,
+    placement: elf_module::Placement::new(),
+    protector: None,
+    cache_sync: None,
 };
 
+static mut ELF_LAST_ERROR: LoadErrorCode = LoadErrorCode::OK;
+
 extern "C" {
     // need to be impl which used in console.rs
     fn rust_console_putbytes(bs: *const u8, len: usize);
@@ -35,16 +64,99 @@ extern "C" {
 
 use core::ptr;
 use core::slice;
+/* the image size is taken from its own headers, prefer rust_elf_load_buf */
 #[no_mangle]
 pub unsafe extern "C" fn rust_elf_load(elf_buf: *const u8) -> *const ElfModule {
-    match ELFFile::parse(elf_buf) {
-        Ok(elf_file) => ELF_MODULE_ROOT.load_elf_file(&elf_file),
-        Err(err) => {
-            println!("Elf parse err:{:?}", err);
-            None
+    rust_elf_load_buf(elf_buf, ELFFile::image_size(elf_buf))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_load_buf(elf_buf: *const u8, len: usize) -> *const ElfModule {
+    rust_elf_load_ex(elf_buf, len, ptr::null_mut())
+}
+
+/* `err` is optional, the result is also kept for rust_elf_last_error */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_load_ex(
+    elf_buf: *const u8,
+    len: usize,
+    err: *mut LoadErrorCode,
+) -> *const ElfModule {
+    load_elf_file_with(ELFFile::parse(elf_buf, len), err)
+}
+
+/* `read` fills `len` bytes at `offset` of the image into `buf` and returns 0 on success */
+pub type ElfReadFn =
+    unsafe extern "C" fn(ctx: *mut u8, offset: usize, buf: *mut u8, len: usize) -> i32;
+
+struct ElfReadCallback {
+    read: ElfReadFn,
+    ctx: *mut u8,
+    size: usize,
+}
+
+impl ElfRead for ElfReadCallback {
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), ELFFileError> {
+        match unsafe { (self.read)(self.ctx, offset, buf.as_mut_ptr(), buf.len()) } {
+            0 => Ok(()),
+            _ => Err(ELFFileError::ReadFailed(offset)),
         }
     }
-    .unwrap_or(ptr::null())
+}
+
+/* load without staging the whole image, only headers and tables are kept in RAM */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_load_stream(
+    read: Option<ElfReadFn>,
+    ctx: *mut u8,
+    size: usize,
+    err: *mut LoadErrorCode,
+) -> *const ElfModule {
+    match read {
+        Some(read) => {
+            let reader = ElfReadCallback { read, ctx, size };
+            load_elf_file_with(ELFFile::from_reader(&reader), err)
+        }
+        None => load_elf_file_with(Err(ELFFileError::FileNotFound), err),
+    }
+}
+
+unsafe fn load_elf_file_with(
+    elf_file: Result<ELFFile, ELFFileError>,
+    err: *mut LoadErrorCode,
+) -> *const ElfModule {
+    let (elf_module, code) = match elf_file
+        .map_err(LoadError::from)
+        .and_then(|elf_file| ELF_MODULE_ROOT.load_elf_file(&elf_file))
+    {
+        Ok(elf_module) => (elf_module, LoadErrorCode::OK),
+        Err(load_err) => {
+            println!("Elf load err:{:?}", load_err);
+            (ptr::null(), load_err.code())
+        }
+    };
+    ELF_LAST_ERROR = code;
+    if let Some(err) = err.as_mut() {
+        *err = code;
+    }
+    elf_module
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_last_error() -> LoadErrorCode {
+    ELF_LAST_ERROR
+}
+
+#[no_mangle]
+pub extern "C" fn rust_elf_strerror(err: i32) -> *const u8 {
+    LoadErrorCode::from_raw(err)
+        .map(|code| code.message())
+        .unwrap_or("unknown error\0")
+        .as_ptr()
 }
 
 /* ensure symbol_name is valid str */
@@ -61,6 +173,287 @@ pub unsafe extern "C" fn rust_elf_sym(
         .unwrap_or(ptr::null())
 }
 
+/* number of RELA sections left unapplied since their target section isn't loaded */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_skipped_relas(elf_module: *const ElfModule) -> usize {
+    elf_module.as_ref().map_or(0, |em| em.skipped_relas.len())
+}
+
+/* name of the `idx`th skipped RELA section, null if out of range */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_skipped_rela(
+    elf_module: *const ElfModule,
+    idx: usize,
+) -> *const u8 {
+    elf_module
+        .as_ref()
+        .and_then(|em| em.skipped_relas.get(idx))
+        .map_or(ptr::null(), |name| name.as_ptr() as *const u8)
+}
+
+/* `name` is copied, the same name can't be registered with another address */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_register_symbol(
+    name: *const u8,
+    addr: *const u8,
+) -> LoadErrorCode {
+    match ELF_MODULE_ROOT.register_symbol(cstr2ruststr(name), addr) {
+        Ok(()) => LoadErrorCode::OK,
+        Err(err) => err.code(),
+    }
+}
+
+/* `allow` is an optional array of `nallow` names, only they are imported if given */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_import_symbols(
+    elf_buf: *const u8,
+    len: usize,
+    allow: *const *const u8,
+    nallow: usize,
+) -> LoadErrorCode {
+    import_symbols_with(ELFFile::parse_executable(elf_buf, len), allow, nallow)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_import_symbols_stream(
+    read: Option<ElfReadFn>,
+    ctx: *mut u8,
+    size: usize,
+    allow: *const *const u8,
+    nallow: usize,
+) -> LoadErrorCode {
+    match read {
+        Some(read) => {
+            let reader = ElfReadCallback { read, ctx, size };
+            import_symbols_with(ELFFile::executable_from_reader(&reader), allow, nallow)
+        }
+        None => import_symbols_with(Err(ELFFileError::FileNotFound), allow, nallow),
+    }
+}
+
+unsafe fn import_symbols_with(
+    elf_file: Result<ELFFile, ELFFileError>,
+    allow: *const *const u8,
+    nallow: usize,
+) -> LoadErrorCode {
+    let allow = (!allow.is_null()).then(|| {
+        slice::from_raw_parts(allow, nallow)
+            .iter()
+            .map(|&name| cstr2ruststr(name))
+            .collect::<alloc::vec::Vec<_>>()
+    });
+    match elf_file
+        .map_err(LoadError::from)
+        .and_then(|elf_file| ELF_MODULE_ROOT.import_symbols(&elf_file, allow.as_deref()))
+    {
+        Ok(n) => {
+            println!("[success]import {} host symbols", n);
+            LoadErrorCode::OK
+        }
+        Err(import_err) => {
+            println!("Elf import err:{:?}", import_err);
+            import_err.code()
+        }
+    }
+}
+
+/* non-zero to load and relocate .debug_* sections of modules loaded later */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_set_load_debug(enable: i32) {
+    ELF_MODULE_ROOT.load_debug = enable != 0;
+}
+
+/* fill `info` of the module which `addr` lies in, return 0 on success */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_addr_info(addr: *const u8, info: *mut AddrInfo) -> i32 {
+    match (ELF_MODULE_ROOT.addr_info(addr), info.as_mut()) {
+        (Some(addr_info), Some(info)) => {
+            *info = addr_info;
+            0
+        }
+        _ => -1,
+    }
+}
+
+/* `resolve` returns null if `name` is unknown, `missing` gets names which are still missing */
+pub type ElfResolveFn = unsafe extern "C" fn(ctx: *mut u8, name: *const u8) -> *const u8;
+pub type ElfMissingFn = unsafe extern "C" fn(ctx: *mut u8, names: *const *const u8, count: usize);
+
+struct ResolverCallback {
+    resolve: Option<ElfResolveFn>,
+    missing: Option<ElfMissingFn>,
+    ctx: *mut u8,
+}
+
+impl SymbolResolver for ResolverCallback {
+    fn resolve(&self, name: &str) -> Option<*const u8> {
+        let resolve = self.resolve?;
+        let name = CString::new(name).ok()?;
+        let symaddr = unsafe { resolve(self.ctx, name.as_ptr() as *const u8) };
+        (!symaddr.is_null()).then(|| symaddr)
+    }
+
+    fn missing(&self, names: &[&str]) {
+        if let Some(missing) = self.missing {
+            let names = names
+                .iter()
+                .map(|&name| CString::new(name).unwrap_or_default())
+                .collect::<Vec<_>>();
+            let ptrs = names
+                .iter()
+                .map(|name| name.as_ptr() as *const u8)
+                .collect::<Vec<_>>();
+            unsafe { missing(self.ctx, ptrs.as_ptr(), ptrs.len()) }
+        }
+    }
+}
+
+/* replace the resolver, both callbacks null to remove it */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_set_resolver(
+    resolve: Option<ElfResolveFn>,
+    missing: Option<ElfMissingFn>,
+    ctx: *mut u8,
+) {
+    ELF_MODULE_ROOT.resolver = match (resolve, missing) {
+        (None, None) => None,
+        _ => Some(Box::new(ResolverCallback {
+            resolve,
+            missing,
+            ctx,
+        })),
+    };
+}
+
+/* `alloc` returns null if out of memory, memory needn't be zeroed */
+pub type ElfRegionAllocFn =
+    unsafe extern "C" fn(ctx: *mut u8, size: usize, align: usize) -> *mut u8;
+pub type ElfRegionFreeFn = unsafe extern "C" fn(ctx: *mut u8, ptr: *mut u8);
+
+struct RegionCallback {
+    alloc: ElfRegionAllocFn,
+    free: ElfRegionFreeFn,
+    ctx: *mut u8,
+}
+
+impl RegionAllocator for RegionCallback {
+    fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+        unsafe { (self.alloc)(self.ctx, layout.size(), layout.align()) }
+    }
+
+    fn dealloc(&self, ptr: *mut u8, _layout: core::alloc::Layout) {
+        unsafe { (self.free)(self.ctx, ptr) }
+    }
+}
+
+/* add a memory region, return its id for rust_elf_add_placement or -1 */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_add_region(
+    alloc: Option<ElfRegionAllocFn>,
+    free: Option<ElfRegionFreeFn>,
+    ctx: *mut u8,
+) -> i32 {
+    match (alloc, free) {
+        (Some(alloc), Some(free)) => ELF_MODULE_ROOT
+            .placement
+            .add_region(Box::new(RegionCallback { alloc, free, ctx }))
+            as i32,
+        _ => -1,
+    }
+}
+
+/* place sections matching `pattern` and `flags` in `region` for modules loaded later,
+ * rules are tried in adding order, return 0 on success */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_add_placement(
+    pattern: *const u8,
+    flags_mask: usize,
+    flags: usize,
+    region: i32,
+) -> i32 {
+    if pattern.is_null() || region < 0 {
+        return -1;
+    }
+    let rule = PlacementRule {
+        pattern: cstr2ruststr(pattern).into(),
+        flags_mask,
+        flags,
+        region: region as usize,
+    };
+    ELF_MODULE_ROOT.placement.add_rule(rule).map_or(-1, |_| 0)
+}
+
+/* `prot` of ELF_PROT_*, return 0 on success */
+pub type ElfProtectFn =
+    unsafe extern "C" fn(ctx: *mut u8, addr: *mut u8, size: usize, prot: u32) -> i32;
+
+struct ProtectCallback {
+    protect: ElfProtectFn,
+    ctx: *mut u8,
+}
+
+impl MemoryProtector for ProtectCallback {
+    fn protect(&self, ptr: *mut u8, size: usize, prot: u32) -> bool {
+        unsafe { (self.protect)(self.ctx, ptr, size, prot) == 0 }
+    }
+}
+
+/* replace the protector, null to remove it, return -1 if any module is loaded
+ * since it must revert what it applied */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_set_protect(protect: Option<ElfProtectFn>, ctx: *mut u8) -> i32 {
+    if !ELF_MODULE_ROOT.modules.is_empty() {
+        return -1;
+    }
+    ELF_MODULE_ROOT.protector = protect
+        .map(|protect| Box::new(ProtectCallback { protect, ctx }) as Box<dyn MemoryProtector>);
+    0
+}
+
+/* make `size` bytes at `addr` visible to instruction fetch */
+pub type ElfCacheSyncFn = unsafe extern "C" fn(ctx: *mut u8, addr: *const u8, size: usize);
+
+struct CacheSyncCallback {
+    sync: ElfCacheSyncFn,
+    ctx: *mut u8,
+}
+
+impl CacheSync for CacheSyncCallback {
+    fn sync(&self, ptr: *const u8, size: usize) {
+        unsafe { (self.sync)(self.ctx, ptr, size) }
+    }
+}
+
+/* replace fence.i by `sync`, null to restore it */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_set_cache_sync(sync: Option<ElfCacheSyncFn>, ctx: *mut u8) {
+    ELF_MODULE_ROOT.cache_sync =
+        sync.map(|sync| Box::new(CacheSyncCallback { sync, ctx }) as Box<dyn CacheSync>);
+}
+
+/* same lookup as rust_elf_sym, return 0 and fill `info` if found */
+#[no_mangle]
+pub unsafe extern "C" fn rust_elf_sym_info(
+    elf_module: *const ElfModule,
+    symbol_name: *const u8,
+    info: *mut SymbolInfo,
+) -> i32 {
+    let symname = cstr2ruststr(symbol_name);
+    match (
+        elf_module
+            .as_ref()
+            .and_then(|em| em.find_symbol_info(symname))
+            .or_else(|| ELF_MODULE_ROOT.find_symbol_info(symname)),
+        info.as_mut(),
+    ) {
+        (Some(sym_info), Some(info)) => {
+            *info = sym_info;
+            0
+        }
+        _ => -1,
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn rust_elf_unload(elf_module: *const ElfModule) {
     ELF_MODULE_ROOT.unload_elf_module(elf_module);